@@ -70,6 +70,7 @@ async function initAudio() {
                 type: 'fftData',
                 data: data.data,
                 limiterReduction: data.limiterReduction,
+                truePeakDb: data.truePeakDb,
                 sbrActive: data.sbrActive
             }).catch(() => { }); // Ignore errors if popup is closed
         } else if (event.data.type === 'wasmError') {
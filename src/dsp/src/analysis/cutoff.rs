@@ -0,0 +1,256 @@
+use crate::config::CUTOFF_CONFIG;
+
+// Typical lowpass frequencies of MP3 encoders and the bitrate they go with,
+// (highest cutoff in Hz, kbps). Above the last entry the source counts as 320 kbps.
+const BITRATE_TABLE: [(f32, u32); 8] = [
+    (8500.0, 48),
+    (11500.0, 64),
+    (13800.0, 80),
+    (15300.0, 96),
+    (16300.0, 128),
+    (17800.0, 160),
+    (19200.0, 192),
+    (19800.0, 256),
+];
+
+/// Finds the brick-wall lowpass of lossy sources from a running average of FFT
+/// magnitude frames. The average is taken in dB so a single transient frame cannot
+/// smear energy above the cutoff for long, and silent frames are left out. Each frame
+/// scores every candidate edge by the level drop across it; a cutoff is only
+/// reported (or dropped, or moved) after the confidence has held past its
+/// threshold for several frames in a row.
+pub struct CutoffDetector {
+    bin_hz: f32,
+    levels_db: Vec<f32>,
+    primed: bool,
+    // Running sum of the averaged levels as power, so band means weight peaks and
+    // sparse spectra are not dragged down by the gaps between partials
+    prefix_power: Vec<f64>,
+
+    cutoff_hz: Option<f32>,
+    confidence: f32,
+    // Candidate waiting out the hold, and for how many frames it has held
+    pending: Option<Option<f32>>,
+    pending_frames: usize,
+}
+
+impl CutoffDetector {
+    /// `fft_size` and `sample_rate` of the frames passed to `process`.
+    pub fn new(fft_size: usize, sample_rate: f32) -> Self {
+        let bins = fft_size / 2;
+        Self {
+            bin_hz: sample_rate / fft_size as f32,
+            levels_db: vec![0.0; bins],
+            primed: false,
+            prefix_power: vec![0.0; bins + 1],
+            cutoff_hz: None,
+            confidence: 0.0,
+            pending: None,
+            pending_frames: 0,
+        }
+    }
+
+    /// Current estimate, `None` for a full-band source or before the first detection.
+    pub fn cutoff_hz(&self) -> Option<f32> {
+        self.cutoff_hz
+    }
+
+    /// Confidence of the latest frame's best edge, 0 to 1.
+    pub fn confidence(&self) -> f32 {
+        self.confidence
+    }
+
+    /// Encoder bitrate that usually comes with the current cutoff, 0 when there is none.
+    pub fn estimated_bitrate_kbps(&self) -> u32 {
+        match self.cutoff_hz {
+            Some(cutoff) => BITRATE_TABLE
+                .iter()
+                .find(|&&(max_cutoff, _)| cutoff <= max_cutoff)
+                .map_or(320, |&(_, kbps)| kbps),
+            None => 0,
+        }
+    }
+
+    /// Feeds the magnitudes of one FFT frame (bins 0 to fft_size / 2).
+    pub fn process(&mut self, magnitudes: &[f32]) {
+        let bins = self.levels_db.len().min(magnitudes.len());
+        let width = self.edge_width();
+        let low = self.bin(CUTOFF_CONFIG.search_low_hz).max(width);
+        let high = self.bin(CUTOFF_CONFIG.search_high_hz).min(bins.saturating_sub(width));
+        if low >= high {
+            return;
+        }
+
+        // Too quiet to judge: keep the current estimate and the average as they are
+        let reference = magnitudes[..low].iter().map(|m| m * m).sum::<f32>() / low as f32;
+        if 10.0 * reference.max(1.0e-20).log10() < CUTOFF_CONFIG.silence_db {
+            return;
+        }
+
+        let averaging = if self.primed { CUTOFF_CONFIG.averaging } else { 0.0 };
+        self.primed = true;
+        for (level, &magnitude) in self.levels_db.iter_mut().zip(&magnitudes[..bins]) {
+            let frame_db = 10.0 * (magnitude * magnitude).max(1.0e-20).log10();
+            *level = averaging * *level + (1.0 - averaging) * frame_db;
+        }
+        self.prefix_power[0] = 0.0;
+        for k in 0..bins {
+            self.prefix_power[k + 1] = self.prefix_power[k] + 10.0f64.powf(self.levels_db[k] as f64 / 10.0);
+        }
+
+        let (candidate, confidence) = self.find_edge(low, high, width, bins);
+        self.confidence = confidence;
+
+        let target = if confidence >= CUTOFF_CONFIG.engage_confidence {
+            Some(candidate)
+        } else if confidence <= CUTOFF_CONFIG.release_confidence {
+            None
+        } else {
+            self.cutoff_hz // Between the thresholds nothing changes
+        };
+        self.update_estimate(target);
+    }
+
+    // Best edge between bins `low` and `high` as (frequency, confidence).
+    fn find_edge(&self, low: usize, high: usize, width: usize, bins: usize) -> (f32, f32) {
+        // The edge is where the level below it most exceeds the level above it
+        let (edge, drop_db) = (low..high)
+            .map(|k| (k, self.mean_db(k - width, k) - self.mean_db(k, k + width)))
+            .fold((low, f32::MIN), |best, edge| if edge.1 > best.1 { edge } else { best });
+
+        // A brick wall stays down all the way up, a natural rolloff comes back
+        let below = self.mean_db(edge - width, edge);
+        let rest = self.mean_db(edge, bins);
+        let depth = (below - rest).min(drop_db);
+        let confidence = ((depth - CUTOFF_CONFIG.min_drop_db)
+            / (CUTOFF_CONFIG.full_drop_db - CUTOFF_CONFIG.min_drop_db))
+            .clamp(0.0, 1.0);
+
+        // Place the cutoff just above the highest bin still over halfway down the
+        // slope, searching from the top so gaps between partials do not count
+        let halfway = below - 0.5 * drop_db;
+        let crossing = (edge - width..edge + width)
+            .rev()
+            .find(|&k| self.levels_db[k] >= halfway)
+            .map_or(edge, |k| k + 1);
+        (crossing as f32 * self.bin_hz, confidence)
+    }
+
+    fn update_estimate(&mut self, target: Option<f32>) {
+        let tolerance = CUTOFF_CONFIG.tolerance_hz;
+        let same = |a: Option<f32>, b: Option<f32>| match (a, b) {
+            (Some(a), Some(b)) => (a - b).abs() <= tolerance,
+            (None, None) => true,
+            _ => false,
+        };
+
+        if same(target, self.cutoff_hz) {
+            self.pending = None;
+            self.pending_frames = 0;
+            return;
+        }
+        match self.pending {
+            Some(pending) if same(pending, target) => self.pending_frames += 1,
+            _ => {
+                self.pending = Some(target);
+                self.pending_frames = 1;
+            }
+        }
+        if self.pending_frames >= CUTOFF_CONFIG.hold_frames {
+            self.cutoff_hz = target;
+            self.pending = None;
+            self.pending_frames = 0;
+        }
+    }
+
+    fn mean_db(&self, start: usize, end: usize) -> f32 {
+        let mean = (self.prefix_power[end] - self.prefix_power[start]) / (end - start).max(1) as f64;
+        (10.0 * mean.max(1.0e-20).log10()) as f32
+    }
+
+    fn edge_width(&self) -> usize {
+        self.bin(CUTOFF_CONFIG.edge_width_hz).max(1)
+    }
+
+    fn bin(&self, frequency: f32) -> usize {
+        (frequency / self.bin_hz) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FFT_SIZE: usize = 4096;
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    // Magnitude frames of music-like noise falling 6 dB/octave above 1 kHz, with a random
+    // ripple per bin and frame. Above `cutoff_hz` only an encoder noise floor 80 dB down is left.
+    fn frames(cutoff_hz: Option<f32>, count: usize) -> Vec<Vec<f32>> {
+        let mut seed = 0x2545_f491u32;
+        let mut random = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed as f32 / u32::MAX as f32
+        };
+        let bin_hz = SAMPLE_RATE / FFT_SIZE as f32;
+        (0..count)
+            .map(|_| {
+                (0..FFT_SIZE / 2)
+                    .map(|k| {
+                        let frequency = k as f32 * bin_hz;
+                        let level = 1.0 / (1.0 + frequency / 1000.0) * (0.25 + 1.5 * random());
+                        match cutoff_hz {
+                            Some(cutoff) if frequency >= cutoff => level * 1.0e-4,
+                            _ => level,
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn detect(frames: &[Vec<f32>]) -> CutoffDetector {
+        let mut detector = CutoffDetector::new(FFT_SIZE, SAMPLE_RATE);
+        for frame in frames {
+            detector.process(frame);
+        }
+        detector
+    }
+
+    #[test]
+    fn finds_a_16k_brick_wall() {
+        let detector = detect(&frames(Some(16000.0), 20));
+        let cutoff = detector.cutoff_hz().expect("cutoff should be detected");
+        assert!((cutoff - 16000.0).abs() <= 2.0 * SAMPLE_RATE / FFT_SIZE as f32, "cutoff {cutoff}");
+        assert!(detector.confidence() > CUTOFF_CONFIG.engage_confidence);
+        assert_eq!(detector.estimated_bitrate_kbps(), 128);
+    }
+
+    #[test]
+    fn ignores_a_full_band_source() {
+        let detector = detect(&frames(None, 20));
+        assert_eq!(detector.cutoff_hz(), None);
+        assert!(detector.confidence() < CUTOFF_CONFIG.release_confidence);
+        assert_eq!(detector.estimated_bitrate_kbps(), 0);
+    }
+
+    #[test]
+    fn waits_out_the_hold_before_reporting() {
+        let frames = frames(Some(16000.0), CUTOFF_CONFIG.hold_frames);
+        let detector = detect(&frames[..CUTOFF_CONFIG.hold_frames - 1]);
+        assert_eq!(detector.cutoff_hz(), None);
+        assert!(detect(&frames).cutoff_hz().is_some());
+    }
+
+    #[test]
+    fn keeps_the_estimate_through_silence() {
+        let mut detector = detect(&frames(Some(16000.0), 20));
+        let silence = vec![0.0; FFT_SIZE / 2];
+        for _ in 0..20 {
+            detector.process(&silence);
+        }
+        assert!(detector.cutoff_hz().is_some());
+    }
+}
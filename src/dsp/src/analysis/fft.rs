@@ -1,6 +1,5 @@
 use rustfft::{FftPlanner, num_complex::Complex};
 use rustfft::num_traits::Zero;
-use std::sync::Arc;
 
 pub struct FftAnalyzer {
     planner: FftPlanner<f32>,
@@ -14,10 +13,10 @@ impl FftAnalyzer {
     pub fn new(size: usize) -> Self {
         let mut window = vec![0.0; size];
         // Hann window
-        for i in 0..size {
+        for (i, w) in window.iter_mut().enumerate() {
             let n = i as f32;
             let n_max = (size - 1) as f32;
-            window[i] = 0.5 * (1.0 - (2.0 * std::f32::consts::PI * n / n_max).cos());
+            *w = 0.5 * (1.0 - (2.0 * std::f32::consts::PI * n / n_max).cos());
         }
 
         Self {
@@ -1 +1,2 @@
+pub mod cutoff;
 pub mod fft;
@@ -0,0 +1,281 @@
+use wasm_bindgen::prelude::*;
+use crate::filters::iir::FilterType;
+
+// Q used when a shelf or pass filter line does not give one (Butterworth).
+const DEFAULT_Q: f32 = 0.707;
+
+pub struct ApoFilter {
+    pub filter_type: FilterType,
+    pub frequency: f32,
+    pub gain: f32,
+    pub q: f32,
+}
+
+pub struct ApoProfile {
+    pub preamp_db: f32,
+    pub filters: Vec<ApoFilter>,
+}
+
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct ApoParseError {
+    line: usize,
+    message: String,
+}
+
+#[wasm_bindgen]
+impl ApoParseError {
+    /// 1-based line number of the offending line.
+    #[wasm_bindgen(getter)]
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn message(&self) -> String {
+        self.message.clone()
+    }
+}
+
+impl ApoParseError {
+    fn new(line: usize, message: impl Into<String>) -> Self {
+        Self { line, message: message.into() }
+    }
+}
+
+/// Parses an Equalizer APO `ParametricEQ.txt` profile (the AutoEQ export format).
+///
+/// Understands `Preamp:` and `Filter:`/`Filter N:` lines. Comments (`#`), blank lines
+/// and other APO commands (`Channel:`, `Device:`, ...) are skipped. Filters switched
+/// `OFF` are dropped. Fails on the first malformed line or when more than `max_filters`
+/// active filters are present.
+pub fn parse_parametric_eq(text: &str, max_filters: usize) -> Result<ApoProfile, ApoParseError> {
+    let mut profile = ApoProfile { preamp_db: 0.0, filters: Vec::new() };
+
+    for (index, raw) in text.lines().enumerate() {
+        let line_no = index + 1;
+        let line = raw.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let Some((command, rest)) = line.split_once(':') else {
+            return Err(ApoParseError::new(line_no, format!("expected 'Preamp:' or 'Filter:', found '{}'", line)));
+        };
+        let command = command.trim();
+
+        if command.eq_ignore_ascii_case("preamp") {
+            // APO applies multiple preamp lines cumulatively
+            profile.preamp_db += parse_preamp(rest, line_no)?;
+        } else if is_filter_command(command) {
+            if let Some(filter) = parse_filter(rest, line_no)? {
+                if profile.filters.len() >= max_filters {
+                    return Err(ApoParseError::new(
+                        line_no,
+                        format!("too many filters, at most {} are supported", max_filters),
+                    ));
+                }
+                profile.filters.push(filter);
+            }
+        }
+    }
+
+    Ok(profile)
+}
+
+// "Filter" optionally followed by a filter number
+fn is_filter_command(command: &str) -> bool {
+    let mut words = command.split_whitespace();
+    let is_filter = words.next().is_some_and(|w| w.eq_ignore_ascii_case("filter"));
+    let number_ok = match words.next() {
+        None => true,
+        Some(n) => n.parse::<u32>().is_ok() && words.next().is_none(),
+    };
+    is_filter && number_ok
+}
+
+fn parse_preamp(rest: &str, line_no: usize) -> Result<f32, ApoParseError> {
+    let mut tokens = rest.split_whitespace();
+    let value = parse_number(tokens.next(), "Preamp", line_no)?;
+    match tokens.next() {
+        None => {}
+        Some(unit) if unit.eq_ignore_ascii_case("db") => {}
+        Some(other) => return Err(ApoParseError::new(line_no, format!("unexpected '{}' after preamp value", other))),
+    }
+    if let Some(other) = tokens.next() {
+        return Err(ApoParseError::new(line_no, format!("unexpected '{}' after preamp value", other)));
+    }
+    Ok(value)
+}
+
+fn parse_filter(rest: &str, line_no: usize) -> Result<Option<ApoFilter>, ApoParseError> {
+    let mut tokens = rest.split_whitespace().peekable();
+
+    let enabled = match tokens.next() {
+        Some(state) if state.eq_ignore_ascii_case("on") => true,
+        Some(state) if state.eq_ignore_ascii_case("off") => false,
+        Some(other) => return Err(ApoParseError::new(line_no, format!("expected ON or OFF, found '{}'", other))),
+        None => return Err(ApoParseError::new(line_no, "missing filter state (ON/OFF)")),
+    };
+
+    let type_token = tokens
+        .next()
+        .ok_or_else(|| ApoParseError::new(line_no, "missing filter type"))?;
+    let filter_type = match type_token.to_ascii_uppercase().as_str() {
+        "PK" | "PEQ" => FilterType::Peaking,
+        "LS" | "LSC" => FilterType::LowShelf,
+        "HS" | "HSC" => FilterType::HighShelf,
+        "LP" | "LPQ" => FilterType::LowPass,
+        "HP" | "HPQ" => FilterType::HighPass,
+        "BP" => FilterType::BandPass,
+        "NO" => FilterType::Notch,
+        "AP" => FilterType::AllPass,
+        _ => return Err(ApoParseError::new(line_no, format!("unsupported filter type '{}'", type_token))),
+    };
+
+    let mut frequency = None;
+    let mut gain = None;
+    let mut q = None;
+    while let Some(key) = tokens.next() {
+        match key.to_ascii_uppercase().as_str() {
+            "FC" => {
+                frequency = Some(parse_number(tokens.next(), "Fc", line_no)?);
+                tokens.next_if(|unit| unit.eq_ignore_ascii_case("hz"));
+            }
+            "GAIN" => {
+                gain = Some(parse_number(tokens.next(), "Gain", line_no)?);
+                tokens.next_if(|unit| unit.eq_ignore_ascii_case("db"));
+            }
+            "Q" => {
+                q = Some(parse_number(tokens.next(), "Q", line_no)?);
+            }
+            _ => return Err(ApoParseError::new(line_no, format!("unexpected '{}'", key))),
+        }
+    }
+
+    if !enabled {
+        return Ok(None);
+    }
+
+    let frequency = frequency.ok_or_else(|| ApoParseError::new(line_no, "missing Fc"))?;
+    if frequency <= 0.0 {
+        return Err(ApoParseError::new(line_no, "Fc must be positive"));
+    }
+    let q = match (q, filter_type) {
+        (Some(q), _) if q <= 0.0 => return Err(ApoParseError::new(line_no, "Q must be positive")),
+        (Some(q), _) => q,
+        (None, FilterType::Peaking) => return Err(ApoParseError::new(line_no, "missing Q")),
+        (None, _) => DEFAULT_Q,
+    };
+    let gain = match filter_type {
+        FilterType::Peaking | FilterType::LowShelf | FilterType::HighShelf => {
+            gain.ok_or_else(|| ApoParseError::new(line_no, "missing Gain"))?
+        }
+        _ => gain.unwrap_or(0.0),
+    };
+
+    Ok(Some(ApoFilter { filter_type, frequency, gain, q }))
+}
+
+fn parse_number(token: Option<&str>, name: &str, line_no: usize) -> Result<f32, ApoParseError> {
+    let token = token.ok_or_else(|| ApoParseError::new(line_no, format!("missing value for {}", name)))?;
+    match token.parse::<f32>() {
+        Ok(value) if value.is_finite() => Ok(value),
+        _ => Err(ApoParseError::new(line_no, format!("invalid {} value '{}'", name, token))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // AutoEQ export for a pair of headphones, as downloaded
+    const AUTOEQ_PROFILE: &str = "\
+Preamp: -6.4 dB
+Filter 1: ON LSC Fc 105 Hz Gain 5.6 dB Q 0.70
+Filter 2: ON PK Fc 196 Hz Gain -2.9 dB Q 0.41
+Filter 3: ON PK Fc 1355 Hz Gain 2.3 dB Q 1.90
+Filter 4: ON PK Fc 3158 Hz Gain -3.2 dB Q 2.77
+Filter 5: ON PK Fc 5464 Hz Gain 4.8 dB Q 3.66
+Filter 6: ON PK Fc 7062 Hz Gain -4.0 dB Q 4.41
+Filter 7: ON PK Fc 8873 Hz Gain 2.7 dB Q 2.01
+Filter 8: ON PK Fc 1188 Hz Gain -1.1 dB Q 1.64
+Filter 9: ON PK Fc 2153 Hz Gain 0.6 dB Q 1.30
+Filter 10: ON HSC Fc 10000 Hz Gain -2.4 dB Q 0.70
+";
+
+    fn parse(text: &str) -> ApoProfile {
+        parse_parametric_eq(text, 31).unwrap_or_else(|e| panic!("line {}: {}", e.line, e.message))
+    }
+
+    fn parse_error(text: &str) -> ApoParseError {
+        parse_parametric_eq(text, 31).err().expect("profile should be rejected")
+    }
+
+    #[test]
+    fn parses_autoeq_profile() {
+        let profile = parse(AUTOEQ_PROFILE);
+        assert_eq!(profile.preamp_db, -6.4);
+        assert_eq!(profile.filters.len(), 10);
+
+        let first = &profile.filters[0];
+        assert_eq!(first.filter_type, FilterType::LowShelf);
+        assert_eq!((first.frequency, first.gain, first.q), (105.0, 5.6, 0.7));
+        let peak = &profile.filters[3];
+        assert_eq!(peak.filter_type, FilterType::Peaking);
+        assert_eq!((peak.frequency, peak.gain, peak.q), (3158.0, -3.2, 2.77));
+        assert_eq!(profile.filters[9].filter_type, FilterType::HighShelf);
+    }
+
+    #[test]
+    fn skips_comments_other_commands_and_off_filters() {
+        let profile = parse(
+            "# Left channel only\nDevice: Speakers\nChannel: L\n\nFilter: OFF PK Fc 100 Hz Gain 3 dB Q 1\nFilter: ON HP Fc 30 Hz\n",
+        );
+        assert_eq!(profile.preamp_db, 0.0);
+        assert_eq!(profile.filters.len(), 1);
+        assert_eq!(profile.filters[0].filter_type, FilterType::HighPass);
+        assert_eq!(profile.filters[0].q, DEFAULT_Q);
+    }
+
+    #[test]
+    fn adds_up_preamp_lines() {
+        let profile = parse("Preamp: -3 dB\nPreamp: -1.5\n");
+        assert_eq!(profile.preamp_db, -4.5);
+
+        let error = parse_error("Preamp: -3 dB\nPreamp: -2 dB extra\n");
+        assert_eq!(error.line, 2);
+        assert_eq!(error.message, "unexpected 'extra' after preamp value");
+    }
+
+    #[test]
+    fn reports_invalid_fc_on_its_line() {
+        let error = parse_error("Preamp: -6.4 dB\n# comment\nFilter 1: ON PK Fc 1O5 Hz Gain 5.6 dB Q 0.70\n");
+        assert_eq!(error.line, 3);
+        assert_eq!(error.message, "invalid Fc value '1O5'");
+
+        let error = parse_error("Filter 1: ON PK Fc -20 Hz Gain 1 dB Q 1\n");
+        assert_eq!(error.message, "Fc must be positive");
+    }
+
+    #[test]
+    fn rejects_unknown_filter_codes() {
+        let error = parse_error("Preamp: -1 dB\nFilter 1: ON XYZ Fc 100 Hz Gain 1 dB Q 1\n");
+        assert_eq!(error.line, 2);
+        assert_eq!(error.message, "unsupported filter type 'XYZ'");
+    }
+
+    #[test]
+    fn rejects_missing_peaking_parameters() {
+        assert_eq!(parse_error("Filter: ON PK Fc 100 Hz Gain 1 dB\n").message, "missing Q");
+        assert_eq!(parse_error("Filter: ON PK Fc 100 Hz Q 1\n").message, "missing Gain");
+        assert_eq!(parse_error("Filter: PK Fc 100 Hz\n").message, "expected ON or OFF, found 'PK'");
+    }
+
+    #[test]
+    fn rejects_more_filters_than_bands() {
+        let error = parse_parametric_eq(AUTOEQ_PROFILE, 4).err().expect("profile should be rejected");
+        assert_eq!(error.line, 6);
+        assert_eq!(error.message, "too many filters, at most 4 are supported");
+    }
+}
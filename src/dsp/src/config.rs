@@ -1,3 +1,54 @@
+use wasm_bindgen::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+
+/// A runtime parameter set that failed validation: the offending field and why.
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct ConfigError {
+    field: String,
+    message: String,
+}
+
+#[wasm_bindgen]
+impl ConfigError {
+    #[wasm_bindgen(getter)]
+    pub fn field(&self) -> String {
+        self.field.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn message(&self) -> String {
+        self.message.clone()
+    }
+}
+
+// Fails with `message` for `field` unless `ok` (NaN comparisons are false, so
+// range checks written as `ok` conditions reject NaN too).
+fn check(ok: bool, field: &str, message: &str) -> Result<(), ConfigError> {
+    if ok {
+        Ok(())
+    } else {
+        Err(ConfigError { field: field.to_string(), message: message.to_string() })
+    }
+}
+
+// Parses a saved config. Malformed JSON is reported against the pseudo-field "json";
+// fields missing from the text keep their defaults.
+fn from_json<T: DeserializeOwned>(json: &str) -> Result<T, ConfigError> {
+    serde_json::from_str(json).map_err(|error| ConfigError { field: "json".to_string(), message: error.to_string() })
+}
+
+fn to_json<T: Serialize>(config: &T) -> String {
+    serde_json::to_string(config).expect("config fields are plain numbers")
+}
+
+/// SBR tuning. Construct from JS with `new SbrConfig()` (the defaults below), change
+/// fields and pass it to `set_sbr_config`; nothing is applied if validation fails.
+/// Saved and restored with `to_json` / `SbrConfig.from_json`.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct SbrConfig {
     pub detection_hp_alpha: f32,
     pub synth_hp_cutoff_hz: f32,
@@ -5,6 +56,15 @@ pub struct SbrConfig {
     pub fast_env_alpha: f32,
     pub slow_env_alpha: f32,
     pub tail_decay: f32,
+    pub transient_ratio: f32,
+    pub synthesis_gain: f32,
+    pub patch_source_low_hz: f32,
+    pub patch_source_high_hz: f32,
+    pub patch_top_hz: f32,
+    pub patch_tilt_db_per_octave: f32,
+    pub patch_max_boost_db: f32,
+    pub fade_in_ms: f32,
+    pub fade_out_ms: f32,
 }
 
 pub const SBR_CONFIG: SbrConfig = SbrConfig {
@@ -14,14 +74,139 @@ pub const SBR_CONFIG: SbrConfig = SbrConfig {
     fast_env_alpha: 0.85,
     slow_env_alpha: 0.992,
     tail_decay: 0.9994,
+    transient_ratio: 1.6,
+    synthesis_gain: 15.0,
+    patch_source_low_hz: 4000.0,
+    patch_source_high_hz: 8000.0,
+    patch_top_hz: 20000.0,
+    patch_tilt_db_per_octave: 6.0,
+    patch_max_boost_db: 12.0,
+    fade_in_ms: 300.0,
+    fade_out_ms: 1000.0,
+};
+
+impl Default for SbrConfig {
+    fn default() -> Self {
+        SBR_CONFIG
+    }
+}
+
+#[wasm_bindgen]
+impl SbrConfig {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Checks ranges and ordering. Frequencies above Nyquist are clamped when applied.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let unit = |x: f32| (0.0..1.0).contains(&x);
+        check(unit(self.detection_hp_alpha), "detection_hp_alpha", "must be in [0, 1)")?;
+        check(self.synth_hp_cutoff_hz > 0.0, "synth_hp_cutoff_hz", "must be positive")?;
+        check(
+            self.synth_lp_cutoff_hz > self.synth_hp_cutoff_hz && self.synth_lp_cutoff_hz.is_finite(),
+            "synth_lp_cutoff_hz",
+            "must be above synth_hp_cutoff_hz",
+        )?;
+        check(unit(self.fast_env_alpha), "fast_env_alpha", "must be in [0, 1)")?;
+        check(
+            unit(self.slow_env_alpha) && self.slow_env_alpha >= self.fast_env_alpha,
+            "slow_env_alpha",
+            "must be in [0, 1) and not faster than fast_env_alpha",
+        )?;
+        check(unit(self.tail_decay), "tail_decay", "must be in [0, 1)")?;
+        check(
+            (1.0..=10.0).contains(&self.transient_ratio),
+            "transient_ratio",
+            "must be between 1 and 10",
+        )?;
+        check(
+            (0.0..=100.0).contains(&self.synthesis_gain),
+            "synthesis_gain",
+            "must be between 0 and 100",
+        )?;
+        check(self.patch_source_low_hz > 0.0, "patch_source_low_hz", "must be positive")?;
+        check(
+            self.patch_source_high_hz > self.patch_source_low_hz,
+            "patch_source_high_hz",
+            "must be above patch_source_low_hz",
+        )?;
+        check(
+            self.patch_top_hz > self.patch_source_high_hz && self.patch_top_hz.is_finite(),
+            "patch_top_hz",
+            "must be above patch_source_high_hz",
+        )?;
+        check(
+            (0.0..=24.0).contains(&self.patch_tilt_db_per_octave),
+            "patch_tilt_db_per_octave",
+            "must be between 0 and 24",
+        )?;
+        check(
+            (0.0..=24.0).contains(&self.patch_max_boost_db),
+            "patch_max_boost_db",
+            "must be between 0 and 24",
+        )?;
+        check((0.0..=10000.0).contains(&self.fade_in_ms), "fade_in_ms", "must be between 0 and 10000")?;
+        check((0.0..=10000.0).contains(&self.fade_out_ms), "fade_out_ms", "must be between 0 and 10000")
+    }
+
+    // By value would consume the JS object
+    #[allow(clippy::wrong_self_convention)]
+    pub fn to_json(&self) -> String {
+        to_json(self)
+    }
+
+    /// Restores a config saved with `to_json`. Fails on malformed JSON or if the
+    /// result does not pass `validate`.
+    pub fn from_json(json: &str) -> Result<SbrConfig, ConfigError> {
+        let config: SbrConfig = from_json(json)?;
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+pub struct CutoffConfig {
+    pub search_low_hz: f32,
+    pub search_high_hz: f32,
+    pub edge_width_hz: f32,
+    pub averaging: f32,
+    pub min_drop_db: f32,
+    pub full_drop_db: f32,
+    pub silence_db: f32,
+    pub engage_confidence: f32,
+    pub release_confidence: f32,
+    pub tolerance_hz: f32,
+    pub hold_frames: usize,
+}
+
+pub const CUTOFF_CONFIG: CutoffConfig = CutoffConfig {
+    search_low_hz: 5000.0,
+    search_high_hz: 21000.0,
+    edge_width_hz: 1000.0,
+    averaging: 0.8,
+    min_drop_db: 20.0,
+    full_drop_db: 40.0,
+    silence_db: -40.0,
+    engage_confidence: 0.6,
+    release_confidence: 0.3,
+    tolerance_hz: 250.0,
+    hold_frames: 4,
 };
 
+/// Limiter tuning, used the same way as `SbrConfig` through `set_limiter_config`.
+/// `threshold` and `knee` are linear, `release_s` in seconds.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct LimiterConfig {
     pub threshold: f32,
     pub knee: f32,
     pub rms_time_ms: f32,
     pub lookahead_ms: f32,
     pub release_s: f32,
+    pub slow_release_factor: f32,
+    pub sustain_ms: f32,
+    pub sustain_depth_db: f32,
 }
 
 pub const LIMITER_CONFIG: LimiterConfig = LimiterConfig {
@@ -30,4 +215,295 @@ pub const LIMITER_CONFIG: LimiterConfig = LimiterConfig {
     rms_time_ms: 50.0,
     lookahead_ms: 2.0,
     release_s: 0.1,
+    slow_release_factor: 8.0,
+    sustain_ms: 500.0,
+    sustain_depth_db: 6.0,
+};
+
+impl Default for LimiterConfig {
+    fn default() -> Self {
+        LIMITER_CONFIG
+    }
+}
+
+#[wasm_bindgen]
+impl LimiterConfig {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        check((0.1..=1.2).contains(&self.threshold), "threshold", "must be between 0.1 and 1.2")?;
+        check(
+            self.knee >= 0.0 && self.knee < self.threshold,
+            "knee",
+            "must be at least 0 and below the threshold",
+        )?;
+        check((0.1..=1000.0).contains(&self.rms_time_ms), "rms_time_ms", "must be between 0.1 and 1000")?;
+        check((0.0..=20.0).contains(&self.lookahead_ms), "lookahead_ms", "must be between 0 and 20")?;
+        check((0.001..=5.0).contains(&self.release_s), "release_s", "must be between 0.001 and 5")?;
+        check(
+            (1.0..=50.0).contains(&self.slow_release_factor),
+            "slow_release_factor",
+            "must be between 1 and 50",
+        )?;
+        check((1.0..=10000.0).contains(&self.sustain_ms), "sustain_ms", "must be between 1 and 10000")?;
+        check(
+            self.sustain_depth_db > 0.0 && self.sustain_depth_db <= 40.0,
+            "sustain_depth_db",
+            "must be above 0 and at most 40",
+        )
+    }
+
+    #[allow(clippy::wrong_self_convention)]
+    pub fn to_json(&self) -> String {
+        to_json(self)
+    }
+
+    /// Restores a config saved with `to_json`, see `SbrConfig::from_json`.
+    pub fn from_json(json: &str) -> Result<LimiterConfig, ConfigError> {
+        let config: LimiterConfig = from_json(json)?;
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+pub struct LinearPhaseConfig {
+    pub num_taps: usize,
+    pub design_fft_size: usize,
+    pub bins_per_block: usize, // Response bins sampled per audio block during a redesign
+}
+
+pub const LINEAR_PHASE_CONFIG: LinearPhaseConfig = LinearPhaseConfig {
+    num_taps: 4095,
+    design_fft_size: 8192,
+    bins_per_block: 512,
+};
+
+pub struct EqConfig {
+    pub default_bands: usize,
+    pub max_bands: usize,
+    pub smoothing_ms: f32,
+    pub headroom_ramp_ms: f32,
+    pub headroom_grid_points: usize,
+}
+
+pub const EQ_CONFIG: EqConfig = EqConfig {
+    default_bands: 11,
+    max_bands: 31,
+    smoothing_ms: 20.0,
+    headroom_ramp_ms: 50.0,
+    headroom_grid_points: 256,
 };
+
+pub struct DynamicEqConfig {
+    pub rms_time_ms: f32,
+    pub max_cut_db: f32,
+}
+
+pub const DYNAMIC_EQ_CONFIG: DynamicEqConfig = DynamicEqConfig {
+    rms_time_ms: 10.0,
+    max_cut_db: 24.0,
+};
+
+pub struct MultibandConfig {
+    pub lowest_crossover_hz: f32,
+    pub highest_crossover_hz: f32,
+    pub crossover_ramp_ms: f32,
+    pub threshold_db: f32,
+    pub ratio: f32,
+    pub knee_db: f32,
+    pub attack_ms: f32,
+    pub release_ms: f32,
+}
+
+pub const MULTIBAND_CONFIG: MultibandConfig = MultibandConfig {
+    lowest_crossover_hz: 150.0,
+    highest_crossover_hz: 5000.0,
+    crossover_ramp_ms: 20.0,
+    threshold_db: -24.0,
+    ratio: 3.0,
+    knee_db: 6.0,
+    attack_ms: 10.0,
+    release_ms: 150.0,
+};
+
+pub struct CompressorConfig {
+    pub threshold_db: f32,
+    pub ratio: f32,
+    pub knee_db: f32,
+    pub attack_ms: f32,
+    pub release_ms: f32,
+}
+
+pub const COMPRESSOR_CONFIG: CompressorConfig = CompressorConfig {
+    threshold_db: -18.0,
+    ratio: 2.0,
+    knee_db: 6.0,
+    attack_ms: 20.0,
+    release_ms: 250.0,
+};
+
+pub struct ClipperConfig {
+    pub ceiling: f32,
+    pub taps_per_phase: usize,
+    pub oversampling_cutoff: f32, // Fraction of the base sample rate
+    pub kaiser_beta: f32,
+}
+
+pub const CLIPPER_CONFIG: ClipperConfig = ClipperConfig {
+    ceiling: 0.99,
+    taps_per_phase: 96,
+    oversampling_cutoff: 0.485,
+    kaiser_beta: 8.0,
+};
+
+pub struct GateConfig {
+    pub threshold_db: f32,
+    pub range_db: f32,
+    pub ratio: f32,
+    pub attack_ms: f32,
+    pub hold_ms: f32,
+    pub release_ms: f32,
+    pub hysteresis_db: f32,
+    pub detector_release_ms: f32,
+}
+
+pub const GATE_CONFIG: GateConfig = GateConfig {
+    threshold_db: -55.0,
+    range_db: 30.0,
+    ratio: 4.0,
+    attack_ms: 2.0,
+    hold_ms: 50.0,
+    release_ms: 200.0,
+    hysteresis_db: 4.0,
+    detector_release_ms: 10.0,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Field<T> = (&'static str, fn(&mut T) -> &mut f32);
+
+    const SBR_FIELDS: [Field<SbrConfig>; 15] = [
+        ("detection_hp_alpha", |c| &mut c.detection_hp_alpha),
+        ("synth_hp_cutoff_hz", |c| &mut c.synth_hp_cutoff_hz),
+        ("synth_lp_cutoff_hz", |c| &mut c.synth_lp_cutoff_hz),
+        ("fast_env_alpha", |c| &mut c.fast_env_alpha),
+        ("slow_env_alpha", |c| &mut c.slow_env_alpha),
+        ("tail_decay", |c| &mut c.tail_decay),
+        ("transient_ratio", |c| &mut c.transient_ratio),
+        ("synthesis_gain", |c| &mut c.synthesis_gain),
+        ("patch_source_low_hz", |c| &mut c.patch_source_low_hz),
+        ("patch_source_high_hz", |c| &mut c.patch_source_high_hz),
+        ("patch_top_hz", |c| &mut c.patch_top_hz),
+        ("patch_tilt_db_per_octave", |c| &mut c.patch_tilt_db_per_octave),
+        ("patch_max_boost_db", |c| &mut c.patch_max_boost_db),
+        ("fade_in_ms", |c| &mut c.fade_in_ms),
+        ("fade_out_ms", |c| &mut c.fade_out_ms),
+    ];
+
+    const LIMITER_FIELDS: [Field<LimiterConfig>; 8] = [
+        ("threshold", |c| &mut c.threshold),
+        ("knee", |c| &mut c.knee),
+        ("rms_time_ms", |c| &mut c.rms_time_ms),
+        ("lookahead_ms", |c| &mut c.lookahead_ms),
+        ("release_s", |c| &mut c.release_s),
+        ("slow_release_factor", |c| &mut c.slow_release_factor),
+        ("sustain_ms", |c| &mut c.sustain_ms),
+        ("sustain_depth_db", |c| &mut c.sustain_depth_db),
+    ];
+
+    // Field named by the error when `field` of the default set is replaced by `value`.
+    fn rejected_field<T: Default>(field: &Field<T>, value: f32, validate: fn(&T) -> Result<(), ConfigError>) -> Option<String> {
+        let mut config = T::default();
+        *(field.1)(&mut config) = value;
+        validate(&config).err().map(|error| error.field())
+    }
+
+    #[test]
+    fn defaults_are_valid() {
+        assert!(SbrConfig::new().validate().is_ok());
+        assert!(LimiterConfig::new().validate().is_ok());
+    }
+
+    #[test]
+    fn rejects_nan_in_every_field() {
+        for field in &SBR_FIELDS {
+            assert_eq!(rejected_field(field, f32::NAN, SbrConfig::validate).as_deref(), Some(field.0));
+        }
+        for field in &LIMITER_FIELDS {
+            assert_eq!(rejected_field(field, f32::NAN, LimiterConfig::validate).as_deref(), Some(field.0));
+        }
+    }
+
+    #[test]
+    fn rejects_out_of_range_values() {
+        let sbr_cases = [
+            ("detection_hp_alpha", 1.0),
+            ("synth_hp_cutoff_hz", 0.0),
+            ("synth_lp_cutoff_hz", f32::INFINITY),
+            ("transient_ratio", 0.5),
+            ("synthesis_gain", -1.0),
+            ("patch_top_hz", 7000.0),
+            ("patch_max_boost_db", 30.0),
+            ("fade_out_ms", 20000.0),
+        ];
+        for (name, value) in sbr_cases {
+            let field = SBR_FIELDS.iter().find(|field| field.0 == name).unwrap();
+            assert_eq!(rejected_field(field, value, SbrConfig::validate).as_deref(), Some(name), "{name} = {value}");
+        }
+
+        let limiter_cases = [
+            ("threshold", 2.0),
+            ("knee", 0.95),
+            ("lookahead_ms", -1.0),
+            ("release_s", 0.0),
+            ("slow_release_factor", 0.5),
+            ("sustain_depth_db", 0.0),
+        ];
+        for (name, value) in limiter_cases {
+            let field = LIMITER_FIELDS.iter().find(|field| field.0 == name).unwrap();
+            assert_eq!(rejected_field(field, value, LimiterConfig::validate).as_deref(), Some(name), "{name} = {value}");
+        }
+    }
+
+    #[test]
+    fn rejects_misordered_pairs() {
+        let config = SbrConfig { slow_env_alpha: 0.5, ..SbrConfig::new() };
+        let error = config.validate().expect_err("slow_env_alpha below fast_env_alpha");
+        assert_eq!(error.field(), "slow_env_alpha");
+        assert_eq!(error.message(), "must be in [0, 1) and not faster than fast_env_alpha");
+
+        let config = SbrConfig { patch_source_high_hz: 3000.0, ..SbrConfig::new() };
+        assert_eq!(config.validate().err().map(|error| error.field()).as_deref(), Some("patch_source_high_hz"));
+    }
+
+    #[test]
+    fn json_round_trips() {
+        let sbr = SbrConfig { synthesis_gain: 7.5, fade_in_ms: 120.0, ..SbrConfig::new() };
+        assert_eq!(SbrConfig::from_json(&sbr.to_json()).unwrap(), sbr);
+        let limiter = LimiterConfig { threshold: 0.8, release_s: 0.25, ..LimiterConfig::new() };
+        assert_eq!(LimiterConfig::from_json(&limiter.to_json()).unwrap(), limiter);
+    }
+
+    #[test]
+    fn json_missing_fields_keep_defaults() {
+        let limiter = LimiterConfig::from_json(r#"{"threshold": 0.9}"#).unwrap();
+        assert_eq!(limiter, LimiterConfig { threshold: 0.9, ..LimiterConfig::new() });
+        assert_eq!(SbrConfig::from_json("{}").unwrap(), SbrConfig::new());
+    }
+
+    #[test]
+    fn json_load_is_validated() {
+        let error = LimiterConfig::from_json(r#"{"threshold": 2.0}"#).expect_err("threshold out of range");
+        assert_eq!(error.field(), "threshold");
+
+        // NaN is saved as null, which does not load back
+        let sbr = SbrConfig { tail_decay: f32::NAN, ..SbrConfig::new() };
+        assert_eq!(SbrConfig::from_json(&sbr.to_json()).expect_err("NaN field").field(), "json");
+        assert_eq!(SbrConfig::from_json("not json").expect_err("malformed").field(), "json");
+    }
+}
@@ -0,0 +1,205 @@
+use wasm_bindgen::prelude::*;
+use crate::config::CLIPPER_CONFIG;
+use crate::filters::design::kaiser_lowpass_taps;
+
+// Rational is the original safety clipper curve and stays the default.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClipCurve {
+    Rational = 0,
+    Tanh = 1,
+    Cubic = 2,
+    Arctan = 3,
+    Hard = 4,
+    Tube = 5,
+}
+
+// Negative half of the tube curve saturates at this fraction of the ceiling,
+// the asymmetry adds even harmonics.
+const TUBE_NEGATIVE_LIMIT: f32 = 0.8;
+
+/// Upsample -> shape -> downsample for one channel, with a shared Kaiser-windowed
+/// sinc lowpass as interpolation and decimation filter, flat to 20 kHz at 44.1/48 kHz. The filter has
+/// `factor * taps_per_phase + 1` taps, so the round trip delays by exactly
+/// `taps_per_phase` samples at the base rate.
+#[derive(Clone, Debug)]
+struct Oversampler {
+    factor: usize,
+    taps: Vec<f32>,
+    input: Vec<f32>, // base-rate history, most recent at `input_pos`
+    input_pos: usize,
+    upsampled: Vec<f32>, // high-rate history, most recent at `upsampled_pos`
+    upsampled_pos: usize,
+}
+
+impl Oversampler {
+    fn new(factor: usize) -> Self {
+        let num_taps = factor * CLIPPER_CONFIG.taps_per_phase + 1;
+        // Cut off a little below the base-rate Nyquist frequency
+        let cutoff = CLIPPER_CONFIG.oversampling_cutoff / factor as f32;
+        let taps = kaiser_lowpass_taps(num_taps, cutoff, CLIPPER_CONFIG.kaiser_beta);
+        Self {
+            factor,
+            taps,
+            input: vec![0.0; CLIPPER_CONFIG.taps_per_phase + 1],
+            input_pos: 0,
+            upsampled: vec![0.0; num_taps],
+            upsampled_pos: 0,
+        }
+    }
+
+    fn process(&mut self, sample: f32, shape: impl Fn(f32) -> f32) -> f32 {
+        self.input_pos = (self.input_pos + 1) % self.input.len();
+        self.input[self.input_pos] = sample;
+
+        let mut output = 0.0;
+        for phase in 0..self.factor {
+            // Zero-stuffed interpolation: only every factor-th tap meets a non-zero sample
+            let mut value = 0.0;
+            for (k, tap) in self.taps.iter().skip(phase).step_by(self.factor).enumerate() {
+                let index = (self.input_pos + self.input.len() - k) % self.input.len();
+                value += tap * self.input[index];
+            }
+            let shaped = shape(value * self.factor as f32);
+
+            self.upsampled_pos = (self.upsampled_pos + 1) % self.upsampled.len();
+            self.upsampled[self.upsampled_pos] = shaped;
+
+            // Decimate on phase 0 so the delay comes out as whole base-rate samples
+            if phase == 0 {
+                let len = self.upsampled.len();
+                output = self
+                    .taps
+                    .iter()
+                    .enumerate()
+                    .map(|(j, tap)| tap * self.upsampled[(self.upsampled_pos + len - j) % len])
+                    .sum();
+            }
+        }
+        output
+    }
+}
+
+/// Safety clipper with selectable curve, drive and ceiling, optionally oversampled.
+#[derive(Clone, Debug)]
+pub struct Clipper {
+    curve: ClipCurve,
+    drive: f32,
+    ceiling: f32,
+    oversampling: usize,
+    oversampler_l: Option<Oversampler>,
+    oversampler_r: Option<Oversampler>,
+}
+
+impl Default for Clipper {
+    fn default() -> Self {
+        Self {
+            curve: ClipCurve::Rational,
+            drive: 1.0,
+            ceiling: CLIPPER_CONFIG.ceiling,
+            oversampling: 1,
+            oversampler_l: None,
+            oversampler_r: None,
+        }
+    }
+}
+
+impl Clipper {
+    /// `drive_db` pushes the signal into the curve (the "warmth" control), `ceiling` is the
+    /// linear output limit, `oversampling` is 1, 2 or 4 (other values round down).
+    pub fn set_params(&mut self, curve: ClipCurve, drive_db: f32, ceiling: f32, oversampling: usize) {
+        self.curve = curve;
+        self.drive = 10.0f32.powf(drive_db / 20.0);
+        self.ceiling = ceiling.clamp(0.1, 1.0);
+
+        let oversampling = match oversampling {
+            0..=1 => 1,
+            2..=3 => 2,
+            _ => 4,
+        };
+        if oversampling != self.oversampling {
+            self.oversampling = oversampling;
+            if oversampling > 1 {
+                self.oversampler_l = Some(Oversampler::new(oversampling));
+                self.oversampler_r = Some(Oversampler::new(oversampling));
+            } else {
+                self.oversampler_l = None;
+                self.oversampler_r = None;
+            }
+        }
+    }
+
+    pub fn latency_samples(&self) -> usize {
+        if self.oversampling > 1 { CLIPPER_CONFIG.taps_per_phase } else { 0 }
+    }
+
+    pub fn process(&mut self, l: f32, r: f32) -> (f32, f32) {
+        let (curve, drive, ceiling) = (self.curve, self.drive, self.ceiling);
+        let shape = |x: f32| clip(curve, x * drive, ceiling);
+        match (self.oversampler_l.as_mut(), self.oversampler_r.as_mut()) {
+            (Some(oversampler_l), Some(oversampler_r)) => {
+                (oversampler_l.process(l, shape), oversampler_r.process(r, shape))
+            }
+            _ => (shape(l), shape(r)),
+        }
+    }
+}
+
+fn clip(curve: ClipCurve, x: f32, ceiling: f32) -> f32 {
+    let u = x / ceiling;
+    match curve {
+        // Linear up to the ceiling, then a rational knee
+        ClipCurve::Rational => {
+            if x > ceiling {
+                ceiling + (x - ceiling) / (1.0 + (x - ceiling))
+            } else if x < -ceiling {
+                -ceiling + (x + ceiling) / (1.0 - (x + ceiling))
+            } else {
+                x
+            }
+        }
+        ClipCurve::Tanh => ceiling * u.tanh(),
+        ClipCurve::Cubic => {
+            // Scaled so the slope at 0 is 1 and the curve flattens at the ceiling
+            let v = (u / 1.5).clamp(-1.0, 1.0);
+            ceiling * 1.5 * (v - v * v * v / 3.0)
+        }
+        ClipCurve::Arctan => ceiling * std::f32::consts::FRAC_2_PI * (u * std::f32::consts::FRAC_PI_2).atan(),
+        ClipCurve::Hard => x.clamp(-ceiling, ceiling),
+        ClipCurve::Tube => {
+            if u >= 0.0 {
+                ceiling * u.tanh()
+            } else {
+                ceiling * TUBE_NEGATIVE_LIMIT * (u / TUBE_NEGATIVE_LIMIT).tanh()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Round-trip gain in dB of a sine through the oversampler with the shaper bypassed,
+    // from the RMS once the filters have settled.
+    fn oversampled_gain_db(factor: usize, frequency: f32, sample_rate: f32) -> f32 {
+        let mut oversampler = Oversampler::new(factor);
+        let omega = 2.0 * std::f64::consts::PI * frequency as f64 / sample_rate as f64;
+        let input: Vec<f32> = (0..8192).map(|n| (omega * n as f64).sin() as f32).collect();
+        let output: Vec<f32> = input.iter().map(|&x| oversampler.process(x, |x| x)).collect();
+        let power = |x: &[f32]| x.iter().map(|v| (v * v) as f64).sum::<f64>();
+        (10.0 * (power(&output[4096..]) / power(&input[4096..])).log10()) as f32
+    }
+
+    #[test]
+    fn oversampling_is_flat_to_20k() {
+        for sample_rate in [44100.0, 48000.0] {
+            for factor in [2, 4] {
+                for frequency in [1000.0, 10000.0, 15000.0, 18000.0, 20000.0] {
+                    let gain = oversampled_gain_db(factor, frequency, sample_rate);
+                    assert!(gain.abs() < 0.1, "{factor}x at {frequency} Hz / {sample_rate}: {gain} dB");
+                }
+            }
+        }
+    }
+}
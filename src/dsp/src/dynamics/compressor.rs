@@ -1,28 +1,211 @@
 use wasm_bindgen::prelude::*;
-use crate::config::LIMITER_CONFIG;
+use super::clipper::{ClipCurve, Clipper};
+use super::limiter::Limiter;
+use super::sidechain::{SidechainFilter, SidechainMode};
+use super::true_peak::{TruePeakDetector, TRUE_PEAK_DELAY};
+use crate::config::{ConfigError, LimiterConfig, COMPRESSOR_CONFIG, LIMITER_CONFIG};
 
 #[wasm_bindgen]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum DetectorMode {
     Peak = 0,
     Rms = 1,
 }
 
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StereoLinkMode {
+    Max = 0,
+    Average = 1,
+}
+
+/// Last stage of the chain: the leveller with its soft safety clipper, or the
+/// brick-wall lookahead limiter.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FinalStage {
+    Leveller = 0,
+    Limiter = 1,
+}
+
+/// How much the two detector channels share their level. At 0 each channel reacts
+/// only to itself, at 1 both react to the linked (max or average) level.
+#[derive(Clone, Copy, Debug)]
+pub struct StereoLink {
+    pub amount: f32,
+    pub mode: StereoLinkMode,
+}
+
+impl StereoLink {
+    pub fn apply(&self, level_a: f32, level_b: f32) -> (f32, f32) {
+        if self.amount <= 0.0 {
+            return (level_a, level_b);
+        }
+        let linked = match self.mode {
+            StereoLinkMode::Max => level_a.max(level_b),
+            StereoLinkMode::Average => (level_a + level_b) * 0.5,
+        };
+        (
+            level_a + (linked - level_a) * self.amount,
+            level_b + (linked - level_b) * self.amount,
+        )
+    }
+}
+
+/// Downward compressor with a dB-domain gain computer: threshold in dBFS, ratio,
+/// soft knee in dB and manual or automatic makeup. Runs ahead of the leveller/limiter.
+#[derive(Clone, Debug)]
+pub struct Compressor {
+    enabled: bool,
+    threshold_db: f32,
+    ratio: f32,
+    knee_db: f32,
+    attack_coeff: f32,
+    release_coeff: f32,
+    makeup_db: f32,
+    auto_makeup: bool,
+    makeup: f32,
+    sample_rate: f32,
+
+    // Smoothed gain reduction in dB per channel, and the deepest since last read
+    reduction_l: f32,
+    reduction_r: f32,
+    min_reduction_db: f32,
+}
+
+impl Compressor {
+    pub fn new(sample_rate: f32) -> Self {
+        let mut c = Self {
+            enabled: false,
+            threshold_db: 0.0,
+            ratio: 1.0,
+            knee_db: 0.0,
+            attack_coeff: 0.0,
+            release_coeff: 0.0,
+            makeup_db: 0.0,
+            auto_makeup: false,
+            makeup: 1.0,
+            sample_rate,
+            reduction_l: 0.0,
+            reduction_r: 0.0,
+            min_reduction_db: 0.0,
+        };
+        c.set_params(
+            COMPRESSOR_CONFIG.threshold_db,
+            COMPRESSOR_CONFIG.ratio,
+            COMPRESSOR_CONFIG.knee_db,
+            COMPRESSOR_CONFIG.attack_ms,
+            COMPRESSOR_CONFIG.release_ms,
+            0.0,
+            true,
+        );
+        c
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.reduction_l = 0.0;
+            self.reduction_r = 0.0;
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// With `auto_makeup` the makeup gain is derived from the curve and `makeup_db` is ignored.
+    #[allow(clippy::too_many_arguments)]
+    pub fn set_params(
+        &mut self,
+        threshold_db: f32,
+        ratio: f32,
+        knee_db: f32,
+        attack_ms: f32,
+        release_ms: f32,
+        makeup_db: f32,
+        auto_makeup: bool,
+    ) {
+        self.threshold_db = threshold_db.min(0.0);
+        self.ratio = ratio.max(1.0);
+        self.knee_db = knee_db.max(0.0);
+        self.attack_coeff = time_coeff(attack_ms, self.sample_rate);
+        self.release_coeff = time_coeff(release_ms, self.sample_rate);
+        self.makeup_db = makeup_db;
+        self.auto_makeup = auto_makeup;
+
+        // Auto makeup restores half of the reduction a full-scale signal would get,
+        // which keeps typical program loudness level without pushing peaks into the limiter.
+        let makeup_db = if auto_makeup {
+            -0.5 * compressor_gain_db(0.0, self.threshold_db, self.ratio, self.knee_db)
+        } else {
+            makeup_db
+        };
+        self.makeup = 10.0f32.powf(makeup_db / 20.0);
+    }
+
+    /// Deepest gain reduction since the last call, in dB (0 or negative).
+    pub fn get_reduction_db(&mut self) -> f32 {
+        std::mem::replace(&mut self.min_reduction_db, 0.0)
+    }
+
+    pub fn process(&mut self, l: f32, r: f32, link: StereoLink) -> (f32, f32) {
+        let (level_l, level_r) = link.apply(l.abs(), r.abs());
+        self.reduction_l = self.smooth(self.reduction_l, level_l);
+        self.reduction_r = self.smooth(self.reduction_r, level_r);
+        self.min_reduction_db = self.min_reduction_db.min(self.reduction_l).min(self.reduction_r);
+
+        let gain_l = 10.0f32.powf(self.reduction_l / 20.0) * self.makeup;
+        let gain_r = 10.0f32.powf(self.reduction_r / 20.0) * self.makeup;
+        (l * gain_l, r * gain_r)
+    }
+
+    // Attack while the reduction deepens, release while it recovers.
+    fn smooth(&self, current: f32, level: f32) -> f32 {
+        let level_db = 20.0 * level.max(1.0e-9).log10();
+        let target = compressor_gain_db(level_db, self.threshold_db, self.ratio, self.knee_db);
+        let coeff = if target < current { self.attack_coeff } else { self.release_coeff };
+        coeff * current + (1.0 - coeff) * target
+    }
+}
+
 #[wasm_bindgen]
 pub struct DynamicsProcessor {
     sample_rate: f32,
+
+    // Compressor stage, ahead of the leveller/limiter
+    compressor: Compressor,
+
+    // Detector linking; with mid_side both stages work on mid and side instead of left and right
+    stereo_link: StereoLink,
+    mid_side: bool,
     
     // Compressor State
     comp_gain_l: f32,
     comp_gain_r: f32,
     rms_l: f32,
     rms_r: f32,
+
+    // Leveller detector filters
+    sidechain_l: SidechainFilter,
+    sidechain_r: SidechainFilter,
     
     // Compressor Params
     attack_coeff: f32,
     release_coeff: f32,
     limiter_enabled: bool,
+    release_ms: f32,
+
+    // Auto release: blends toward a slow release the longer the level has stayed over threshold
+    auto_release: bool,
+    slow_release_coeff: f32,
+    slow_release_factor: f32,
+    sustain_coeff: f32,
+    over_l: f32,
+    over_r: f32,
     min_reduction: f32,
+    min_reduction_l: f32,
+    min_reduction_r: f32,
     detector_mode: DetectorMode,
     knee: f32,
     threshold: f32,
@@ -31,7 +214,21 @@ pub struct DynamicsProcessor {
     lookahead_index: usize,
     lookahead_l: Vec<f32>,
     lookahead_r: Vec<f32>,
-    
+
+    // True peak: optional 4x oversampled limiter detection, and the output meter
+    true_peak_limiting: bool,
+    true_peak_detect_l: TruePeakDetector,
+    true_peak_detect_r: TruePeakDetector,
+    true_peak_meter_l: TruePeakDetector,
+    true_peak_meter_r: TruePeakDetector,
+    max_true_peak: f32,
+
+    final_stage: FinalStage,
+    limiter: Limiter,
+    clipper: Clipper,
+
+    // Last applied tuning, kept in sync by the individual setters
+    limiter_config: LimiterConfig,
 }
 
 #[wasm_bindgen]
@@ -42,14 +239,28 @@ impl DynamicsProcessor {
         let lookahead_len = lookahead_samples.max(1);
         let mut d = Self {
             sample_rate,
+            compressor: Compressor::new(sample_rate),
+            stereo_link: StereoLink { amount: 0.0, mode: StereoLinkMode::Max },
+            mid_side: false,
             comp_gain_l: 1.0,
             comp_gain_r: 1.0,
             rms_l: 0.0,
             rms_r: 0.0,
+            sidechain_l: SidechainFilter::new(sample_rate),
+            sidechain_r: SidechainFilter::new(sample_rate),
             attack_coeff: 0.0, // set in update_coeffs
             release_coeff: 0.0,
             limiter_enabled: true,
+            release_ms: LIMITER_CONFIG.release_s * 1000.0,
+            auto_release: false,
+            slow_release_coeff: 0.0,
+            slow_release_factor: LIMITER_CONFIG.slow_release_factor,
+            sustain_coeff: time_coeff(LIMITER_CONFIG.sustain_ms, sample_rate),
+            over_l: 0.0,
+            over_r: 0.0,
             min_reduction: 1.0,
+            min_reduction_l: 1.0,
+            min_reduction_r: 1.0,
             detector_mode: DetectorMode::Peak,
             knee: LIMITER_CONFIG.knee,
             threshold: LIMITER_CONFIG.threshold,
@@ -58,6 +269,16 @@ impl DynamicsProcessor {
             lookahead_index: 0,
             lookahead_l: vec![0.0; lookahead_len],
             lookahead_r: vec![0.0; lookahead_len],
+            true_peak_limiting: false,
+            true_peak_detect_l: TruePeakDetector::default(),
+            true_peak_detect_r: TruePeakDetector::default(),
+            true_peak_meter_l: TruePeakDetector::default(),
+            true_peak_meter_r: TruePeakDetector::default(),
+            max_true_peak: 0.0,
+            final_stage: FinalStage::Leveller,
+            limiter: Limiter::new(sample_rate),
+            clipper: Clipper::default(),
+            limiter_config: LIMITER_CONFIG,
         };
         d.set_limiter_options(true, 0.1); // Default attack 0.1s
         d.set_limiter_params(
@@ -67,6 +288,8 @@ impl DynamicsProcessor {
             LIMITER_CONFIG.lookahead_ms,
             LIMITER_CONFIG.rms_time_ms,
         );
+        // Both final stages start with the same release, so switching stages keeps it
+        d.limiter.set_release(d.release_ms, d.auto_release);
         d
     }
 
@@ -76,12 +299,50 @@ impl DynamicsProcessor {
         
         let t_interval = 1.0 / self.sample_rate;
         // JS: this.attackCoeff = Math.exp(-tInterval / this.limiterAttack);
-        // JS: this.compRelease = 0.1; 
-        let release = LIMITER_CONFIG.release_s;
         
         let safe_attack = attack.max(0.001);
         self.attack_coeff = (-t_interval / safe_attack).exp();
-        self.release_coeff = (-t_interval / release).exp();
+        self.update_release_coeffs();
+    }
+
+    /// Release in milliseconds for the leveller and the brick-wall limiter. With `auto_release`
+    /// it stretches toward `slow_release_factor` times longer while the
+    /// signal keeps hitting the threshold, so sustained bass does not pump.
+    pub fn set_limiter_release(&mut self, release_ms: f32, auto_release: bool) {
+        self.release_ms = release_ms.max(1.0);
+        self.auto_release = auto_release;
+        self.limiter_config.release_s = self.release_ms / 1000.0;
+        self.update_release_coeffs();
+        self.limiter.set_release(self.release_ms, auto_release);
+    }
+
+    pub fn set_compressor_enabled(&mut self, enabled: bool) {
+        self.compressor.set_enabled(enabled);
+    }
+
+    pub fn is_compressor_enabled(&self) -> bool {
+        self.compressor.is_enabled()
+    }
+
+    /// Threshold in dBFS, knee and makeup in dB, attack/release in milliseconds.
+    /// With `auto_makeup` the makeup gain follows the threshold and ratio instead.
+    #[allow(clippy::too_many_arguments)]
+    pub fn set_compressor_params(
+        &mut self,
+        threshold_db: f32,
+        ratio: f32,
+        knee_db: f32,
+        attack_ms: f32,
+        release_ms: f32,
+        makeup_db: f32,
+        auto_makeup: bool,
+    ) {
+        self.compressor
+            .set_params(threshold_db, ratio, knee_db, attack_ms, release_ms, makeup_db, auto_makeup);
+    }
+
+    pub fn get_compressor_reduction_db(&mut self) -> f32 {
+        self.compressor.get_reduction_db()
     }
 
     pub fn set_limiter_params(
@@ -94,6 +355,10 @@ impl DynamicsProcessor {
     ) {
         self.threshold = threshold.clamp(0.1, 1.2);
         self.knee = knee.max(0.0);
+        self.limiter_config.threshold = self.threshold;
+        self.limiter_config.knee = self.knee;
+        self.limiter_config.lookahead_ms = lookahead_ms;
+        self.limiter_config.rms_time_ms = rms_time_ms;
         self.detector_mode = detector_mode;
         self.rms_coeff = rms_coeff(rms_time_ms, self.sample_rate);
 
@@ -105,8 +370,100 @@ impl DynamicsProcessor {
             self.lookahead_r = vec![0.0; lookahead_len];
             self.lookahead_index = 0;
         }
+        self.limiter.set_params(self.threshold, lookahead_ms);
+    }
+
+    /// Applies a whole limiter parameter set at once; the detector mode and auto release
+    /// switch stay as they are. Nothing changes if the set fails validation.
+    pub fn set_limiter_config(&mut self, config: &LimiterConfig) -> Result<(), ConfigError> {
+        config.validate()?;
+        self.slow_release_factor = config.slow_release_factor;
+        self.sustain_coeff = time_coeff(config.sustain_ms, self.sample_rate);
+        self.limiter.set_auto_release_shape(config.slow_release_factor, config.sustain_ms, config.sustain_depth_db);
+        self.limiter_config = *config;
+        self.set_limiter_params(
+            config.threshold,
+            config.knee,
+            self.detector_mode,
+            config.lookahead_ms,
+            config.rms_time_ms,
+        );
+        self.set_limiter_release(config.release_s * 1000.0, self.auto_release);
+        Ok(())
+    }
+
+    pub fn get_limiter_config(&self) -> LimiterConfig {
+        self.limiter_config
+    }
+
+    /// Filter ahead of the leveller detector: a high-pass at `frequency` so sub-bass does not
+    /// drive the gain reduction, or BS.1770 K-weighting. The audio path is not filtered.
+    pub fn set_sidechain_filter(&mut self, mode: SidechainMode, frequency: f32) {
+        self.sidechain_l.set_mode(mode, frequency);
+        self.sidechain_r.set_mode(mode, frequency);
+    }
+
+    /// Chooses between the leveller/soft-clipper and the brick-wall limiter. Both share
+    /// the threshold and lookahead of `set_limiter_params`. The limiter works on left
+    /// and right even with mid/side detection, so its ceiling holds on the output.
+    pub fn set_final_stage(&mut self, stage: FinalStage) {
+        self.final_stage = stage;
+    }
+
+    pub fn get_final_stage(&self) -> FinalStage {
+        self.final_stage
+    }
+
+    /// Safety clipper after the leveller: curve, drive in dB, linear ceiling and
+    /// 1x/2x/4x oversampling. Oversampling adds to `latency_samples`.
+    pub fn set_clipper_params(&mut self, curve: ClipCurve, drive_db: f32, ceiling: f32, oversampling: usize) {
+        self.clipper.set_params(curve, drive_db, ceiling, oversampling);
     }
     
+    /// Peak-mode leveller detection on the 4x oversampled (true-peak) signal, so
+    /// inter-sample peaks are held under the threshold too.
+    pub fn set_true_peak_limiting(&mut self, enabled: bool) {
+        self.true_peak_limiting = enabled;
+        self.limiter.set_detector_delay(if enabled { TRUE_PEAK_DELAY } else { 0 });
+    }
+
+    /// Highest true-peak level of the output since the last call, in dBTP.
+    pub fn get_true_peak_db(&mut self) -> f32 {
+        let db = 20.0 * self.max_true_peak.max(1.0e-6).log10();
+        self.max_true_peak = 0.0;
+        db
+    }
+
+    pub fn latency_samples(&self) -> usize {
+        if !self.limiter_enabled {
+            return 0;
+        }
+        match self.final_stage {
+            FinalStage::Leveller => self.lookahead_samples + self.clipper.latency_samples(),
+            FinalStage::Limiter => self.limiter.latency_samples(),
+        }
+    }
+
+    /// Stereo link from 0 (independent channels) to 1 (fully linked), for the compressor
+    /// and the leveller. With `mid_side` they detect on and act on mid and side instead.
+    pub fn set_stereo_link(&mut self, amount: f32, mode: StereoLinkMode, mid_side: bool) {
+        self.stereo_link = StereoLink { amount: amount.clamp(0.0, 1.0), mode };
+        self.mid_side = mid_side;
+    }
+
+    /// Leveller reduction of one channel since its last read, in dB: 0 is left (mid),
+    /// 1 is right (side). `get_reduction_db` reports the deepest of both.
+    pub fn get_channel_reduction_db(&mut self, channel: usize) -> f32 {
+        let min_reduction = match channel {
+            0 => &mut self.min_reduction_l,
+            1 => &mut self.min_reduction_r,
+            _ => return 0.0,
+        };
+        let db = 20.0 * min_reduction.log10();
+        *min_reduction = 1.0;
+        db
+    }
+
     pub fn get_reduction_db(&mut self) -> f32 {
         if self.min_reduction < 1.0 {
             let db = 20.0 * self.min_reduction.log10();
@@ -117,15 +474,37 @@ impl DynamicsProcessor {
     }
 
     pub fn process_block(&mut self, left: &mut [f32], right: &mut [f32]) {
-        if !self.limiter_enabled {
+        if !self.limiter_enabled && !self.compressor.is_enabled() {
+            // Still metered, so dBTP can be watched with all dynamics off
+            for (&l, &r) in left.iter().zip(right.iter()) {
+                self.meter_true_peak(l, r);
+            }
             return;
         }
         
         let block_size = left.len().min(right.len());
         
         for i in 0..block_size {
-            let input_l = left[i];
-            let input_r = right[i];
+            let (mut input_l, mut input_r) = (left[i], right[i]);
+            if self.mid_side {
+                (input_l, input_r) = ((input_l + input_r) * 0.5, (input_l - input_r) * 0.5);
+            }
+            if self.compressor.is_enabled() {
+                (input_l, input_r) = self.compressor.process(input_l, input_r, self.stereo_link);
+            }
+            if !self.limiter_enabled {
+                if self.mid_side {
+                    (input_l, input_r) = (input_l + input_r, input_l - input_r);
+                }
+                self.meter_true_peak(input_l, input_r);
+                left[i] = input_l;
+                right[i] = input_r;
+                continue;
+            }
+            if self.final_stage == FinalStage::Limiter {
+                (left[i], right[i]) = self.process_limiter(input_l, input_r);
+                continue;
+            }
             let (mut l, mut r) = if self.lookahead_samples > 0 {
                 let idx = self.lookahead_index;
                 let delayed_l = self.lookahead_l[idx];
@@ -140,8 +519,14 @@ impl DynamicsProcessor {
             
             // --- Stage 1: Compressor (Leveller) ---
             
-            // Left
             let abs_l = self.detector_sample(input_l, true);
+            let abs_r = self.detector_sample(input_r, false);
+            let (abs_l, abs_r) = self.stereo_link.apply(abs_l, abs_r);
+
+            let release_l = self.release_for(abs_l, true);
+            let release_r = self.release_for(abs_r, false);
+
+            // Left
             let mut target_l = 1.0;
             if abs_l > 0.0 {
                 target_l = limiter_gain(abs_l, self.threshold, self.knee);
@@ -150,12 +535,11 @@ impl DynamicsProcessor {
             if target_l < self.comp_gain_l {
                 self.comp_gain_l = self.attack_coeff * self.comp_gain_l + (1.0 - self.attack_coeff) * target_l;
             } else {
-                self.comp_gain_l = self.release_coeff * self.comp_gain_l + (1.0 - self.release_coeff) * target_l;
+                self.comp_gain_l = release_l * self.comp_gain_l + (1.0 - release_l) * target_l;
             }
             l *= self.comp_gain_l;
             
             // Right
-            let abs_r = self.detector_sample(input_r, false);
             let mut target_r = 1.0;
             if abs_r > 0.0 {
                 target_r = limiter_gain(abs_r, self.threshold, self.knee);
@@ -164,36 +548,87 @@ impl DynamicsProcessor {
             if target_r < self.comp_gain_r {
                 self.comp_gain_r = self.attack_coeff * self.comp_gain_r + (1.0 - self.attack_coeff) * target_r;
             } else {
-                self.comp_gain_r = self.release_coeff * self.comp_gain_r + (1.0 - self.release_coeff) * target_r;
+                self.comp_gain_r = release_r * self.comp_gain_r + (1.0 - release_r) * target_r;
             }
             r *= self.comp_gain_r;
             
             // Reduction Tracking
             if self.comp_gain_l < self.min_reduction { self.min_reduction = self.comp_gain_l; }
             if self.comp_gain_r < self.min_reduction { self.min_reduction = self.comp_gain_r; }
-            
-            // --- Stage 2: Safety Clipper ---
-            // Hard/Soft clip at 0.99
-             if l > 0.99 {
-                l = 0.99 + (l - 0.99) / (1.0 + (l - 0.99));
-            } else if l < -0.99 {
-                l = -0.99 + (l + 0.99) / (1.0 - (l + 0.99));
+            if self.comp_gain_l < self.min_reduction_l { self.min_reduction_l = self.comp_gain_l; }
+            if self.comp_gain_r < self.min_reduction_r { self.min_reduction_r = self.comp_gain_r; }
+
+            if self.mid_side {
+                (l, r) = (l + r, l - r);
             }
             
-            if r > 0.99 {
-                r = 0.99 + (r - 0.99) / (1.0 + (r - 0.99));
-            } else if r < -0.99 {
-                r = -0.99 + (r + 0.99) / (1.0 - (r + 0.99));
-            }
+            // --- Stage 2: Safety Clipper ---
+            (l, r) = self.clipper.process(l, r);
             
+            self.meter_true_peak(l, r);
             left[i] = l;
             right[i] = r;
         }
     }
 
+    fn process_limiter(&mut self, mut l: f32, mut r: f32) -> (f32, f32) {
+        if self.mid_side {
+            (l, r) = (l + r, l - r);
+        }
+        let level_l = self.peak_level(l, true);
+        let level_r = self.peak_level(r, false);
+        let (level_l, level_r) = self.stereo_link.apply(level_l, level_r);
+        let (l, r) = self.limiter.process(l, r, level_l, level_r);
+
+        let (gain_l, gain_r) = self.limiter.gains();
+        self.min_reduction = self.min_reduction.min(gain_l).min(gain_r);
+        self.min_reduction_l = self.min_reduction_l.min(gain_l);
+        self.min_reduction_r = self.min_reduction_r.min(gain_r);
+        self.meter_true_peak(l, r);
+        (l, r)
+    }
+
+    fn update_release_coeffs(&mut self) {
+        self.release_coeff = time_coeff(self.release_ms, self.sample_rate);
+        self.slow_release_coeff =
+            time_coeff(self.release_ms * self.slow_release_factor, self.sample_rate);
+    }
+
+    // Release coefficient for one channel. Auto release tracks the share of recent time
+    // spent over threshold: short peaks recover fast, sustained overs slowly.
+    fn release_for(&mut self, level: f32, is_left: bool) -> f32 {
+        if !self.auto_release {
+            return self.release_coeff;
+        }
+        let over = if is_left { &mut self.over_l } else { &mut self.over_r };
+        let hit = if level > self.threshold { 1.0 } else { 0.0 };
+        *over = self.sustain_coeff * *over + (1.0 - self.sustain_coeff) * hit;
+        self.release_coeff + (self.slow_release_coeff - self.release_coeff) * *over
+    }
+
+    fn peak_level(&mut self, sample: f32, is_left: bool) -> f32 {
+        match (self.true_peak_limiting, is_left) {
+            (true, true) => self.true_peak_detect_l.process(sample),
+            (true, false) => self.true_peak_detect_r.process(sample),
+            (false, _) => sample.abs(),
+        }
+    }
+
+    fn meter_true_peak(&mut self, l: f32, r: f32) {
+        let peak = self.true_peak_meter_l.process(l).max(self.true_peak_meter_r.process(r));
+        if peak > self.max_true_peak {
+            self.max_true_peak = peak;
+        }
+    }
+
     fn detector_sample(&mut self, sample: f32, is_left: bool) -> f32 {
+        let sample = if is_left {
+            self.sidechain_l.process(sample)
+        } else {
+            self.sidechain_r.process(sample)
+        };
         match self.detector_mode {
-            DetectorMode::Peak => sample.abs(),
+            DetectorMode::Peak => self.peak_level(sample, is_left),
             DetectorMode::Rms => {
                 if is_left {
                     self.rms_l = self.rms_coeff * self.rms_l + (1.0 - self.rms_coeff) * (sample * sample);
@@ -229,6 +664,27 @@ fn limiter_gain(level: f32, threshold: f32, knee: f32) -> f32 {
     }
 }
 
+/// Static downward-compression curve with a quadratic soft knee: gain change in dB
+/// (0 or negative) for an input level in dB.
+pub fn compressor_gain_db(level_db: f32, threshold_db: f32, ratio: f32, knee_db: f32) -> f32 {
+    let over = level_db - threshold_db;
+    let slope = 1.0 / ratio - 1.0;
+    if 2.0 * over <= -knee_db {
+        0.0
+    } else if 2.0 * over.abs() < knee_db {
+        let x = over + knee_db * 0.5;
+        slope * x * x / (2.0 * knee_db)
+    } else {
+        slope * over
+    }
+}
+
+/// One-pole smoothing coefficient for a time constant in milliseconds.
+pub fn time_coeff(time_ms: f32, sample_rate: f32) -> f32 {
+    let time_s = (time_ms / 1000.0).max(0.0001);
+    (-1.0 / (time_s * sample_rate)).exp()
+}
+
 fn rms_coeff(rms_time_ms: f32, sample_rate: f32) -> f32 {
     let time_s = (rms_time_ms / 1000.0).max(0.001);
     (-1.0 / (time_s * sample_rate)).exp()
@@ -237,3 +693,127 @@ fn rms_coeff(rms_time_ms: f32, sample_rate: f32) -> f32 {
 fn ms_to_samples(ms: f32, sample_rate: f32) -> usize {
     ((ms.max(0.0) / 1000.0) * sample_rate).round() as usize
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gain_curve_is_continuous_at_the_knee_edges() {
+        let (threshold, ratio, knee) = (-20.0, 4.0, 6.0);
+        for edge in [threshold - knee / 2.0, threshold + knee / 2.0] {
+            // At most the curve's own slope across the 2e-4 dB step, no jump
+            let below = compressor_gain_db(edge - 1.0e-4, threshold, ratio, knee);
+            let above = compressor_gain_db(edge + 1.0e-4, threshold, ratio, knee);
+            assert!((above - below).abs() < 2.0e-4, "jump of {} dB at {edge} dB", above - below);
+        }
+        assert_eq!(compressor_gain_db(threshold - knee / 2.0, threshold, ratio, knee), 0.0);
+        // Half way into the knee the curve is 1/8 of the hard-knee gain at the upper edge
+        let middle = compressor_gain_db(threshold, threshold, ratio, knee);
+        assert!((middle - (1.0 / ratio - 1.0) * knee / 8.0).abs() < 1.0e-5, "{middle} dB");
+    }
+
+    #[test]
+    fn output_rises_at_one_over_ratio_above_the_knee() {
+        let (threshold, knee) = (-20.0, 6.0);
+        for ratio in [2.0, 4.0, 10.0] {
+            for knee_db in [0.0, knee] {
+                let output = |level: f32| level + compressor_gain_db(level, threshold, ratio, knee_db);
+                for level in [-15.0, -10.0, 0.0] {
+                    let slope = output(level + 1.0) - output(level);
+                    assert!((slope - 1.0 / ratio).abs() < 1.0e-4, "ratio {ratio}, knee {knee_db}: slope {slope}");
+                }
+                // A hard knee meets the threshold exactly
+                if knee_db == 0.0 {
+                    assert!((output(-10.0) - (threshold + 10.0 / ratio)).abs() < 1.0e-4);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn linked_detectors_give_both_channels_the_same_gain() {
+        // Only the left channel is over the threshold
+        let hot = |n: usize| 0.9 * (2.0 * std::f32::consts::PI * 100.0 * n as f32 / 48000.0).sin();
+        let quiet = |n: usize| 0.01 * (2.0 * std::f32::consts::PI * 330.0 * n as f32 / 48000.0).sin();
+        for (amount, linked) in [(1.0, true), (0.0, false)] {
+            let mut compressor = Compressor::new(48000.0);
+            compressor.set_params(-20.0, 4.0, 0.0, 5.0, 50.0, 0.0, false);
+            let link = StereoLink { amount, mode: StereoLinkMode::Max };
+            let mut max_difference = 0.0f32;
+            for n in 0..48000 {
+                let (l, r) = (hot(n), quiet(n));
+                let (out_l, out_r) = compressor.process(l, r, link);
+                if n > 4800 && l.abs() > 1.0e-3 && r.abs() > 1.0e-4 {
+                    let gain_db = |out: f32, input: f32| 20.0 * (out / input).log10();
+                    max_difference = max_difference.max((gain_db(out_l, l) - gain_db(out_r, r)).abs());
+                }
+            }
+            if linked {
+                assert!(max_difference < 1.0e-3, "gains differ by {max_difference} dB");
+            } else {
+                assert!(max_difference > 6.0, "unlinked quiet channel should not be reduced");
+            }
+        }
+    }
+
+    #[test]
+    fn limiter_stage_holds_the_ceiling() {
+        let mut dynamics = DynamicsProcessor::new(48000.0);
+        dynamics.set_final_stage(FinalStage::Limiter);
+        dynamics.set_stereo_link(1.0, StereoLinkMode::Max, false);
+        let mut left: Vec<f32> = (0..48000)
+            .map(|n| 2.0 * (2.0 * std::f32::consts::PI * 60.0 * n as f32 / 48000.0).sin())
+            .collect();
+        let mut right: Vec<f32> = left.iter().map(|x| 0.5 * x).collect();
+        for (l, r) in left.chunks_mut(128).zip(right.chunks_mut(128)) {
+            dynamics.process_block(l, r);
+        }
+        let peak = left.iter().chain(&right).fold(0.0f32, |peak, x| peak.max(x.abs()));
+        assert!(peak <= LIMITER_CONFIG.threshold + 1.0e-6, "peak {peak}");
+        assert!(peak > 0.9 * LIMITER_CONFIG.threshold, "peak {peak} limited too hard");
+        assert!(dynamics.get_reduction_db() < -6.0);
+    }
+
+    #[test]
+    fn true_peak_limiting_holds_inter_sample_peaks() {
+        // Quarter-rate sine bursts sampled 45 degrees off their peaks: true peak 3 dB over the samples
+        let sine = |n: usize| {
+            let amplitude = if (n / 2400).is_multiple_of(2) { 2.0 } else { 0.3 };
+            amplitude * (std::f32::consts::FRAC_PI_2 * n as f32 + std::f32::consts::FRAC_PI_4).sin()
+        };
+        let ceiling_db = 20.0 * LIMITER_CONFIG.threshold.log10();
+        for true_peak in [false, true] {
+            let mut dynamics = DynamicsProcessor::new(48000.0);
+            dynamics.set_final_stage(FinalStage::Limiter);
+            dynamics.set_true_peak_limiting(true_peak);
+            let mut left: Vec<f32> = (0..48000).map(sine).collect();
+            let mut right = left.clone();
+            for (n, (l, r)) in left.chunks_mut(128).zip(right.chunks_mut(128)).enumerate() {
+                if n == 200 {
+                    dynamics.get_true_peak_db(); // Metered from here on, past the first onsets
+                }
+                dynamics.process_block(l, r);
+            }
+            let over_db = dynamics.get_true_peak_db() - ceiling_db;
+            if true_peak {
+                assert!(over_db < 0.05, "{over_db} dB over the ceiling");
+            } else {
+                assert!(over_db > 2.5, "sample-peak limiting should miss the inter-sample peaks");
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_limiter_config_changes_nothing() {
+        let mut dynamics = DynamicsProcessor::new(48000.0);
+        let config = LimiterConfig { threshold: 0.8, release_s: 0.3, ..LimiterConfig::new() };
+        assert!(dynamics.set_limiter_config(&config).is_ok());
+        assert_eq!(dynamics.get_limiter_config(), config);
+
+        let invalid = LimiterConfig { threshold: 0.5, knee: f32::NAN, ..config };
+        let error = dynamics.set_limiter_config(&invalid).expect_err("NaN knee");
+        assert_eq!(error.field(), "knee");
+        assert_eq!(dynamics.get_limiter_config(), config);
+    }
+}
@@ -0,0 +1,235 @@
+use super::compressor::time_coeff;
+use super::sidechain::{SidechainFilter, SidechainMode};
+use crate::config::GATE_CONFIG;
+
+/// Noise gate / downward expander, stereo-linked. Below the threshold the signal is
+/// attenuated by `(threshold - level) * (ratio - 1)` dB, at most `range_db`; a high
+/// ratio makes it a gate. Once open it stays open until the level falls `hysteresis_db`
+/// under the threshold and the hold time has passed.
+#[derive(Clone, Debug)]
+pub struct Gate {
+    enabled: bool,
+    threshold_db: f32,
+    range_db: f32,
+    ratio: f32,
+    hysteresis_db: f32,
+    attack_coeff: f32,
+    release_coeff: f32,
+    hold_samples: usize,
+    detector_coeff: f32,
+    sample_rate: f32,
+    sidechain_l: SidechainFilter,
+    sidechain_r: SidechainFilter,
+
+    // State
+    envelope: f32,
+    open: bool,
+    hold_left: usize,
+    gain_db: f32,
+    min_gain_db: f32,
+}
+
+impl Gate {
+    pub fn new(sample_rate: f32) -> Self {
+        let mut gate = Self {
+            enabled: false,
+            threshold_db: 0.0,
+            range_db: 0.0,
+            ratio: 1.0,
+            hysteresis_db: 0.0,
+            attack_coeff: 0.0,
+            release_coeff: 0.0,
+            hold_samples: 0,
+            detector_coeff: time_coeff(GATE_CONFIG.detector_release_ms, sample_rate),
+            sample_rate,
+            sidechain_l: SidechainFilter::new(sample_rate),
+            sidechain_r: SidechainFilter::new(sample_rate),
+            envelope: 0.0,
+            open: false,
+            hold_left: 0,
+            gain_db: 0.0,
+            min_gain_db: 0.0,
+        };
+        gate.set_params(
+            GATE_CONFIG.threshold_db,
+            GATE_CONFIG.range_db,
+            GATE_CONFIG.ratio,
+            GATE_CONFIG.attack_ms,
+            GATE_CONFIG.hold_ms,
+            GATE_CONFIG.release_ms,
+            GATE_CONFIG.hysteresis_db,
+        );
+        gate
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.gain_db = 0.0;
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Threshold in dBFS, range and hysteresis in dB, times in milliseconds.
+    #[allow(clippy::too_many_arguments)]
+    pub fn set_params(
+        &mut self,
+        threshold_db: f32,
+        range_db: f32,
+        ratio: f32,
+        attack_ms: f32,
+        hold_ms: f32,
+        release_ms: f32,
+        hysteresis_db: f32,
+    ) {
+        self.threshold_db = threshold_db;
+        self.range_db = range_db.max(0.0);
+        self.ratio = ratio.max(1.0);
+        self.attack_coeff = time_coeff(attack_ms, self.sample_rate);
+        self.release_coeff = time_coeff(release_ms, self.sample_rate);
+        self.hold_samples = ((hold_ms.max(0.0) / 1000.0) * self.sample_rate).round() as usize;
+        self.hysteresis_db = hysteresis_db.max(0.0);
+    }
+
+    /// Band-pass ahead of the detector, so the gate keys on e.g. the voice range
+    /// and ignores rumble or hiss. `enabled = false` detects on the full band.
+    pub fn set_sidechain(&mut self, enabled: bool, frequency: f32, q: f32) {
+        let mode = if enabled { SidechainMode::BandPass } else { SidechainMode::Off };
+        self.sidechain_l.set_mode_with_q(mode, frequency, q);
+        self.sidechain_r.set_mode_with_q(mode, frequency, q);
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// Deepest attenuation since the last call, in dB (0 or negative).
+    pub fn get_reduction_db(&mut self) -> f32 {
+        std::mem::replace(&mut self.min_gain_db, 0.0)
+    }
+
+    pub fn process(&mut self, l: f32, r: f32) -> (f32, f32) {
+        let level = self.sidechain_l.process(l).abs().max(self.sidechain_r.process(r).abs());
+        // Instant attack, short decay so the level does not drop out at zero crossings
+        self.envelope = if level > self.envelope {
+            level
+        } else {
+            self.detector_coeff * self.envelope + (1.0 - self.detector_coeff) * level
+        };
+        let level_db = 20.0 * self.envelope.max(1.0e-9).log10();
+
+        if level_db > self.threshold_db {
+            self.open = true;
+            self.hold_left = self.hold_samples;
+        } else if self.open && level_db < self.threshold_db - self.hysteresis_db {
+            if self.hold_left > 0 {
+                self.hold_left -= 1;
+            } else {
+                self.open = false;
+            }
+        }
+
+        let target = if self.open {
+            0.0
+        } else {
+            ((level_db - self.threshold_db) * (self.ratio - 1.0)).clamp(-self.range_db, 0.0)
+        };
+        // Attack opens the gate, release closes it
+        let coeff = if target > self.gain_db { self.attack_coeff } else { self.release_coeff };
+        self.gain_db = coeff * self.gain_db + (1.0 - coeff) * target;
+        self.min_gain_db = self.min_gain_db.min(self.gain_db);
+
+        let gain = 10.0f32.powf(self.gain_db / 20.0);
+        (l * gain, r * gain)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+    const THRESHOLD_DB: f32 = -40.0;
+    const HYSTERESIS_DB: f32 = 6.0;
+    const RANGE_DB: f32 = 20.0;
+
+    fn gate(hold_ms: f32) -> Gate {
+        let mut gate = Gate::new(SAMPLE_RATE);
+        gate.set_params(THRESHOLD_DB, RANGE_DB, 100.0, 1.0, hold_ms, 20.0, HYSTERESIS_DB);
+        gate.set_enabled(true);
+        gate
+    }
+
+    // Full-scale square wave at `db`, so the detector sees a steady level.
+    fn level(db: f32, n: usize) -> f32 {
+        let amplitude = 10.0f32.powf(db / 20.0);
+        if n.is_multiple_of(2) { amplitude } else { -amplitude }
+    }
+
+    // Runs `samples` of `db` through the gate, returning the last output gain in dB.
+    fn run(gate: &mut Gate, db: f32, samples: usize) -> f32 {
+        let mut gain_db = 0.0;
+        for n in 0..samples {
+            let input = level(db, n);
+            let (l, r) = gate.process(input, input);
+            assert_eq!(l, r);
+            gain_db = 20.0 * (l / input).log10();
+        }
+        gain_db
+    }
+
+    // Samples the gate stays open for after the input drops from open to silence.
+    fn open_time(hold_ms: f32) -> usize {
+        let mut gate = gate(hold_ms);
+        run(&mut gate, -20.0, 4800);
+        assert!(gate.is_open());
+        (0..SAMPLE_RATE as usize)
+            .position(|_| {
+                gate.process(0.0, 0.0);
+                !gate.is_open()
+            })
+            .expect("gate should close")
+    }
+
+    #[test]
+    fn closes_to_the_range_below_the_close_threshold() {
+        let mut gate = gate(10.0);
+        assert!(run(&mut gate, -20.0, 4800).abs() < 1.0e-3);
+        let gain_db = run(&mut gate, -60.0, SAMPLE_RATE as usize);
+        assert!(!gate.is_open());
+        assert!((gain_db + RANGE_DB).abs() < 0.01, "gain {gain_db} dB");
+        assert!((gate.get_reduction_db() + RANGE_DB).abs() < 0.01);
+    }
+
+    #[test]
+    fn stays_open_for_the_hold_time() {
+        let hold_ms = 50.0;
+        let extra = open_time(hold_ms) - open_time(0.0);
+        assert_eq!(extra, (hold_ms / 1000.0 * SAMPLE_RATE) as usize);
+    }
+
+    #[test]
+    fn does_not_chatter_between_the_thresholds() {
+        // Level wandering +-1 dB around the middle of the hysteresis band
+        let mut seed = 0x9e37_79b9u32;
+        let mut wander = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            THRESHOLD_DB - 0.5 * HYSTERESIS_DB + 2.0 * (seed as f32 / u32::MAX as f32 - 0.5)
+        };
+
+        for (start_db, open) in [(-20.0, true), (-60.0, false)] {
+            let mut gate = gate(10.0);
+            run(&mut gate, start_db, 4800);
+            assert_eq!(gate.is_open(), open);
+            for _ in 0..200 {
+                run(&mut gate, wander(), 240);
+                assert_eq!(gate.is_open(), open, "gate started {}", if open { "open" } else { "closed" });
+            }
+        }
+    }
+}
@@ -1,81 +1,233 @@
+use std::collections::VecDeque;
+use super::compressor::time_coeff;
+use crate::config::LIMITER_CONFIG;
+
+// Gain path of one channel: sliding-window minimum over the lookahead, program-dependent
+// release, then a moving average over the lookahead so the gain ramps down smoothly and
+// reaches the required value exactly when the peak leaves the delay line.
+#[derive(Clone, Debug)]
+struct GainChannel {
+    // Sliding minimum: (sample index, gain), gains increasing from front to back
+    window: VecDeque<(usize, f32)>,
+    released: f32,
+    sustain_db: f32,
+    average: Vec<f32>,
+    average_sum: f64,
+    average_pos: usize,
+    delay: Vec<f32>,
+    delay_pos: usize,
+    gain: f32,
+}
+
+impl GainChannel {
+    fn new(window: usize, delay: usize) -> Self {
+        Self {
+            window: VecDeque::with_capacity(window + 1),
+            released: 1.0,
+            sustain_db: 0.0,
+            average: vec![1.0; window],
+            average_sum: window as f64,
+            average_pos: 0,
+            delay: vec![0.0; delay.max(1)],
+            delay_pos: 0,
+            gain: 1.0,
+        }
+    }
+}
+
+/// Brick-wall lookahead limiter. The output never exceeds `threshold` on the detected level;
+/// the audio is delayed by the lookahead plus any detector delay, see `latency_samples`.
 #[derive(Clone, Debug)]
 pub struct Limiter {
     threshold: f32,
-    knee: f32,
-    attack_coeff: f32,
-    release_coeff: f32,
-    envelope: f32,
-    lookahead_buffer: Vec<f32>,
-    lookahead_pos: usize,
+    lookahead: usize,
+    detector_delay: usize,
+    fast_release_coeff: f32,
+    slow_release_coeff: f32,
+    auto_release: bool,
+    release_ms: f32,
+    slow_release_factor: f32,
+    sustain_coeff: f32,
+    sustain_depth_db: f32,
+    channels: [GainChannel; 2],
+    counter: usize,
     sample_rate: f32,
-    min_gain: f32,
 }
 
 impl Limiter {
     pub fn new(sample_rate: f32) -> Self {
-        Self {
-            threshold: 0.95, // -0.5 dB
-            knee: 0.5,       // Soft knee width
-            attack_coeff: (-1.0 / (sample_rate * 0.005)).exp(), // 5ms attack
-            release_coeff: (-1.0 / (sample_rate * 0.100)).exp(), // 100ms release
-            envelope: 0.0,
-            lookahead_buffer: vec![0.0; (sample_rate * 0.005) as usize], // 5ms lookahead
-            lookahead_pos: 0,
+        let release_ms = LIMITER_CONFIG.release_s * 1000.0;
+        let mut limiter = Self {
+            threshold: LIMITER_CONFIG.threshold,
+            lookahead: 0,
+            detector_delay: 0,
+            fast_release_coeff: time_coeff(release_ms, sample_rate),
+            slow_release_coeff: time_coeff(release_ms * LIMITER_CONFIG.slow_release_factor, sample_rate),
+            auto_release: false,
+            release_ms,
+            slow_release_factor: LIMITER_CONFIG.slow_release_factor,
+            sustain_coeff: time_coeff(LIMITER_CONFIG.sustain_ms, sample_rate),
+            sustain_depth_db: LIMITER_CONFIG.sustain_depth_db,
+            channels: [GainChannel::new(1, 1), GainChannel::new(1, 1)],
+            counter: 0,
             sample_rate,
-            min_gain: 1.0,
-        }
+        };
+        limiter.set_params(LIMITER_CONFIG.threshold, LIMITER_CONFIG.lookahead_ms);
+        limiter
     }
 
-    pub fn set_params(&mut self, threshold: f32, knee: f32) {
+    pub fn set_params(&mut self, threshold: f32, lookahead_ms: f32) {
         self.threshold = threshold;
-        self.knee = knee;
+        let lookahead = (((lookahead_ms.max(0.0) / 1000.0) * self.sample_rate).round() as usize).max(1);
+        self.resize(lookahead, self.detector_delay);
     }
-    
-    pub fn get_and_reset_min_gain(&mut self) -> f32 {
-        let val = self.min_gain;
-        self.min_gain = 1.0;
-        val
+
+    /// With `auto_release` the release slows toward `slow_release_factor` times longer
+    /// the deeper and longer the limiting has been; otherwise it is fixed.
+    pub fn set_release(&mut self, release_ms: f32, auto_release: bool) {
+        self.release_ms = release_ms;
+        self.fast_release_coeff = time_coeff(release_ms, self.sample_rate);
+        self.slow_release_coeff = time_coeff(release_ms * self.slow_release_factor, self.sample_rate);
+        self.auto_release = auto_release;
+    }
+
+    /// Shape of the auto release: how much slower it gets, how long the reduction is
+    /// averaged over and how deep that average must be for the full slowdown.
+    pub fn set_auto_release_shape(&mut self, slow_release_factor: f32, sustain_ms: f32, sustain_depth_db: f32) {
+        self.slow_release_factor = slow_release_factor;
+        self.sustain_coeff = time_coeff(sustain_ms, self.sample_rate);
+        self.sustain_depth_db = sustain_depth_db;
+        self.set_release(self.release_ms, self.auto_release);
+    }
+
+    /// How many samples the detection levels trail the audio (e.g. an oversampling
+    /// true-peak detector); the audio is delayed to match.
+    pub fn set_detector_delay(&mut self, detector_delay: usize) {
+        self.resize(self.lookahead, detector_delay);
     }
 
-    pub fn process(&mut self, input: f32) -> f32 {
-        let abs_input = input.abs();
-        
-        // Envelope follower with attack/release
-        if abs_input > self.envelope {
-            self.envelope = self.attack_coeff * self.envelope + (1.0 - self.attack_coeff) * abs_input;
+    pub fn latency_samples(&self) -> usize {
+        self.lookahead + self.detector_delay
+    }
+
+    /// Gains applied to the last returned samples.
+    pub fn gains(&self) -> (f32, f32) {
+        (self.channels[0].gain, self.channels[1].gain)
+    }
+
+    /// Limits one stereo sample. `level_l`/`level_r` are the detection levels for the
+    /// input samples, already linked by the caller if wanted.
+    pub fn process(&mut self, l: f32, r: f32, level_l: f32, level_r: f32) -> (f32, f32) {
+        let index = self.counter;
+        self.counter = self.counter.wrapping_add(1);
+        let out_l = self.process_channel(0, index, l, level_l);
+        let out_r = self.process_channel(1, index, r, level_r);
+        (out_l, out_r)
+    }
+
+    fn process_channel(&mut self, channel: usize, index: usize, input: f32, level: f32) -> f32 {
+        let target = if level > self.threshold { self.threshold / level } else { 1.0 };
+        let lookahead = self.lookahead;
+        let fast = self.fast_release_coeff;
+        let slow = if self.auto_release { self.slow_release_coeff } else { fast };
+        let sustain_coeff = self.sustain_coeff;
+        let sustain_depth_db = self.sustain_depth_db;
+        let state = &mut self.channels[channel];
+
+        // 1. Minimum gain over the last `lookahead + 1` samples, so the average below only
+        //    ever sees gains at or under the one needed when the peak comes out
+        while state.window.back().is_some_and(|&(_, gain)| gain >= target) {
+            state.window.pop_back();
+        }
+        state.window.push_back((index, target));
+        while state.window.front().is_some_and(|&(i, _)| index.wrapping_sub(i) > lookahead) {
+            state.window.pop_front();
+        }
+        let held = state.window.front().map_or(1.0, |&(_, gain)| gain);
+
+        // 2. Instant attack, release slowing down the longer and deeper the limiting has been
+        if held < state.released {
+            state.released = held;
         } else {
-            self.envelope = self.release_coeff * self.envelope + (1.0 - self.release_coeff) * abs_input;
+            let depth = (state.sustain_db / sustain_depth_db).clamp(0.0, 1.0);
+            let coeff = fast + (slow - fast) * depth;
+            state.released = coeff * state.released + (1.0 - coeff) * held;
         }
+        let reduction_db = -20.0 * state.released.max(1.0e-6).log10();
+        state.sustain_db = sustain_coeff * state.sustain_db + (1.0 - sustain_coeff) * reduction_db;
 
-        // Calculate gain reduction
-        let mut gain = 1.0;
-        
-        // Soft knee limiting curve
-        if self.envelope > self.threshold {
-            let excess = self.envelope - self.threshold;
-            
-            if excess < self.knee {
-                // Soft knee region
-                gain = self.threshold / self.envelope;
-            } else {
-                // Hard limiting region
-                gain = self.threshold / self.envelope;
-            }
+        // 3. Moving average over the lookahead
+        let oldest = state.average[state.average_pos];
+        state.average[state.average_pos] = state.released;
+        state.average_pos = (state.average_pos + 1) % state.average.len();
+        state.average_sum += state.released as f64 - oldest as f64;
+        if state.average_pos == 0 {
+            // Resync the running sum once per lap so rounding cannot drift
+            state.average_sum = state.average.iter().map(|&g| g as f64).sum();
+        }
+        state.gain = ((state.average_sum / state.average.len() as f64) as f32).min(1.0);
+
+        // 4. Delay the audio so the ramp lines up with the peak
+        let delayed = state.delay[state.delay_pos];
+        state.delay[state.delay_pos] = input;
+        state.delay_pos = (state.delay_pos + 1) % state.delay.len();
+
+        delayed * state.gain
+    }
+
+    fn resize(&mut self, lookahead: usize, detector_delay: usize) {
+        if lookahead == self.lookahead && detector_delay == self.detector_delay {
+            return;
         }
-        
-        if gain < self.min_gain {
-            self.min_gain = gain;
+        self.lookahead = lookahead;
+        self.detector_delay = detector_delay;
+        let delay = lookahead + detector_delay;
+        self.channels = [GainChannel::new(lookahead, delay), GainChannel::new(lookahead, delay)];
+        self.counter = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    // Loud, bursty test signal: a low tone with sparse single-sample spikes up to +12 dB.
+    fn loud_signal(len: usize) -> Vec<f32> {
+        let mut seed = 0x1234_5678u32;
+        (0..len)
+            .map(|n| {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                let tone = 1.5 * (2.0 * std::f32::consts::PI * 80.0 * n as f32 / SAMPLE_RATE).sin();
+                if seed.is_multiple_of(500) { 4.0 * tone.signum() } else { tone }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn output_stays_under_the_ceiling() {
+        for auto_release in [false, true] {
+            let mut limiter = Limiter::new(SAMPLE_RATE);
+            limiter.set_release(50.0, auto_release);
+            for sample in loud_signal(48000) {
+                let (l, r) = limiter.process(sample, -sample, sample.abs(), sample.abs());
+                assert!(l.abs() <= LIMITER_CONFIG.threshold + 1.0e-6, "{l} over the ceiling");
+                assert!(r.abs() <= LIMITER_CONFIG.threshold + 1.0e-6, "{r} over the ceiling");
+            }
         }
+    }
 
-        // Apply look-ahead delay
-        let delayed_input = self.lookahead_buffer[self.lookahead_pos];
-        self.lookahead_buffer[self.lookahead_pos] = input;
-        
-        self.lookahead_pos += 1;
-        if self.lookahead_pos >= self.lookahead_buffer.len() {
-            self.lookahead_pos = 0;
+    #[test]
+    fn passes_quiet_signal_unchanged_after_the_lookahead() {
+        let mut limiter = Limiter::new(SAMPLE_RATE);
+        let latency = limiter.latency_samples();
+        let input: Vec<f32> = (0..4800).map(|n| 0.5 * (n as f32 * 0.05).sin()).collect();
+        let output: Vec<f32> = input.iter().map(|&x| limiter.process(x, x, x.abs(), x.abs()).0).collect();
+        for (out, expected) in output[latency..].iter().zip(&input) {
+            assert_eq!(out, expected);
         }
-        
-        delayed_input * gain
     }
 }
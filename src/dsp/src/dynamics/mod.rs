@@ -1,2 +1,7 @@
 pub mod limiter;
 pub mod compressor;
+pub mod multiband;
+pub mod true_peak;
+pub mod clipper;
+pub mod sidechain;
+pub mod gate;
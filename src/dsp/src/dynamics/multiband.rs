@@ -0,0 +1,354 @@
+use super::compressor::{compressor_gain_db, time_coeff};
+use crate::config::MULTIBAND_CONFIG;
+use crate::filters::iir::{BiquadFilter, FilterType};
+
+const MAX_BANDS: usize = 5;
+const MIN_BANDS: usize = 3;
+const BUTTERWORTH_Q: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+#[derive(Clone, Copy, Debug)]
+struct BandParams {
+    threshold_db: f32,
+    ratio: f32,
+    knee_db: f32,
+    attack_coeff: f32,
+    release_coeff: f32,
+    makeup: f32,
+    solo: bool,
+    bypass: bool,
+}
+
+// Fourth-order Linkwitz-Riley section: two identical Butterworth biquads.
+#[derive(Clone, Debug)]
+struct Lr4 {
+    sections: [BiquadFilter; 2],
+}
+
+impl Lr4 {
+    fn new(filter_type: FilterType, frequency: f32, sample_rate: f32) -> Self {
+        let mut lr4 = Self {
+            sections: [BiquadFilter::new(sample_rate), BiquadFilter::new(sample_rate)],
+        };
+        for section in &mut lr4.sections {
+            section.set_ramp_time(MULTIBAND_CONFIG.crossover_ramp_ms);
+        }
+        lr4.set_frequency(filter_type, frequency);
+        lr4
+    }
+
+    fn set_frequency(&mut self, filter_type: FilterType, frequency: f32) {
+        for section in &mut self.sections {
+            section.set_params(filter_type, frequency, BUTTERWORTH_Q, 0.0);
+        }
+    }
+
+    fn process(&mut self, input: f32) -> f32 {
+        let sample = self.sections[0].process(input);
+        self.sections[1].process(sample)
+    }
+}
+
+// One channel of the crossover tree. Each split peels the lowest band off the rest;
+// a band below crossover k goes through the allpasses of crossovers k+1.. so that it
+// picks up the same phase as the bands split off later, and the sum stays flat.
+#[derive(Clone, Debug)]
+struct CrossoverChannel {
+    lows: Vec<Lr4>,
+    highs: Vec<Lr4>,
+    allpasses: Vec<Vec<BiquadFilter>>,
+}
+
+impl CrossoverChannel {
+    fn new(crossovers: &[f32], sample_rate: f32) -> Self {
+        let lows = crossovers
+            .iter()
+            .map(|&freq| Lr4::new(FilterType::LowPass, freq, sample_rate))
+            .collect();
+        let highs = crossovers
+            .iter()
+            .map(|&freq| Lr4::new(FilterType::HighPass, freq, sample_rate))
+            .collect();
+        let allpasses = (0..crossovers.len())
+            .map(|band| {
+                crossovers[band + 1..]
+                    .iter()
+                    .map(|&freq| {
+                        // LP4 + HP4 of a Linkwitz-Riley pair is a second-order Butterworth-Q allpass
+                        let mut allpass = BiquadFilter::new(sample_rate);
+                        allpass.set_ramp_time(MULTIBAND_CONFIG.crossover_ramp_ms);
+                        allpass.set_params(FilterType::AllPass, freq, BUTTERWORTH_Q, 0.0);
+                        allpass
+                    })
+                    .collect()
+            })
+            .collect();
+        Self { lows, highs, allpasses }
+    }
+
+    fn set_crossover(&mut self, index: usize, frequency: f32) {
+        self.lows[index].set_frequency(FilterType::LowPass, frequency);
+        self.highs[index].set_frequency(FilterType::HighPass, frequency);
+        for (band, allpasses) in self.allpasses.iter_mut().enumerate().take(index) {
+            allpasses[index - band - 1].set_params(FilterType::AllPass, frequency, BUTTERWORTH_Q, 0.0);
+        }
+    }
+
+    fn split(&mut self, input: f32, bands: &mut [f32; MAX_BANDS]) {
+        let mut rest = input;
+        for (k, (low, high)) in self.lows.iter_mut().zip(self.highs.iter_mut()).enumerate() {
+            let mut band = low.process(rest);
+            for allpass in &mut self.allpasses[k] {
+                band = allpass.process(band);
+            }
+            bands[k] = band;
+            rest = high.process(rest);
+        }
+        bands[self.lows.len()] = rest;
+    }
+}
+
+/// Multiband downward compressor: a Linkwitz-Riley crossover into 3-5 bands, each with its
+/// own compressor, summed back to a flat response. Detection is stereo-linked per band.
+#[derive(Clone, Debug)]
+pub struct MultibandCompressor {
+    enabled: bool,
+    sample_rate: f32,
+    crossovers: Vec<f32>,
+    split_l: CrossoverChannel,
+    split_r: CrossoverChannel,
+    params: [BandParams; MAX_BANDS],
+
+    // Per-band state: smoothed gain reduction in dB and the deepest reduction since last read
+    reduction_db: [f32; MAX_BANDS],
+    max_reduction_db: [f32; MAX_BANDS],
+}
+
+impl MultibandCompressor {
+    pub fn new(sample_rate: f32) -> Self {
+        let crossovers = default_crossovers(MIN_BANDS);
+        let mut compressor = Self {
+            enabled: false,
+            sample_rate,
+            split_l: CrossoverChannel::new(&crossovers, sample_rate),
+            split_r: CrossoverChannel::new(&crossovers, sample_rate),
+            crossovers,
+            params: [BandParams {
+                threshold_db: 0.0,
+                ratio: 1.0,
+                knee_db: 0.0,
+                attack_coeff: 0.0,
+                release_coeff: 0.0,
+                makeup: 1.0,
+                solo: false,
+                bypass: false,
+            }; MAX_BANDS],
+            reduction_db: [0.0; MAX_BANDS],
+            max_reduction_db: [0.0; MAX_BANDS],
+        };
+        for band in 0..MAX_BANDS {
+            compressor.set_band_params(
+                band,
+                MULTIBAND_CONFIG.threshold_db,
+                MULTIBAND_CONFIG.ratio,
+                MULTIBAND_CONFIG.knee_db,
+                MULTIBAND_CONFIG.attack_ms,
+                MULTIBAND_CONFIG.release_ms,
+                0.0,
+            );
+        }
+        compressor
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn band_count(&self) -> usize {
+        self.crossovers.len() + 1
+    }
+
+    /// Sets 3 to 5 bands. Crossovers are reset to their default spacing.
+    pub fn set_band_count(&mut self, count: usize) {
+        let count = count.clamp(MIN_BANDS, MAX_BANDS);
+        if count == self.band_count() {
+            return;
+        }
+        self.crossovers = default_crossovers(count);
+        self.split_l = CrossoverChannel::new(&self.crossovers, self.sample_rate);
+        self.split_r = CrossoverChannel::new(&self.crossovers, self.sample_rate);
+        self.reduction_db = [0.0; MAX_BANDS];
+    }
+
+    /// Moves crossover `index` (0 is the lowest). Clamped between its neighbours.
+    pub fn set_crossover(&mut self, index: usize, frequency: f32) {
+        if index >= self.crossovers.len() {
+            return;
+        }
+        let lower = if index > 0 { self.crossovers[index - 1] } else { 20.0 };
+        let upper = match self.crossovers.get(index + 1) {
+            Some(&next) => next,
+            None => self.sample_rate * 0.45,
+        };
+        let frequency = frequency.clamp(lower, upper);
+        self.crossovers[index] = frequency;
+        self.split_l.set_crossover(index, frequency);
+        self.split_r.set_crossover(index, frequency);
+    }
+
+    pub fn crossover(&self, index: usize) -> f32 {
+        self.crossovers.get(index).copied().unwrap_or(0.0)
+    }
+
+    /// Threshold in dBFS, knee width and makeup in dB, attack/release in milliseconds.
+    #[allow(clippy::too_many_arguments)]
+    pub fn set_band_params(
+        &mut self,
+        band: usize,
+        threshold_db: f32,
+        ratio: f32,
+        knee_db: f32,
+        attack_ms: f32,
+        release_ms: f32,
+        makeup_db: f32,
+    ) {
+        let sample_rate = self.sample_rate;
+        if let Some(params) = self.params.get_mut(band) {
+            params.threshold_db = threshold_db;
+            params.ratio = ratio.max(1.0);
+            params.knee_db = knee_db.max(0.0);
+            params.attack_coeff = time_coeff(attack_ms, sample_rate);
+            params.release_coeff = time_coeff(release_ms, sample_rate);
+            params.makeup = 10.0f32.powf(makeup_db / 20.0);
+        }
+    }
+
+    /// While any band is soloed only the soloed bands are heard.
+    pub fn set_band_solo(&mut self, band: usize, solo: bool) {
+        if let Some(params) = self.params.get_mut(band) {
+            params.solo = solo;
+        }
+    }
+
+    /// A bypassed band passes through the crossover uncompressed and without makeup.
+    pub fn set_band_bypass(&mut self, band: usize, bypass: bool) {
+        if let Some(params) = self.params.get_mut(band) {
+            params.bypass = bypass;
+        }
+    }
+
+    /// Deepest gain reduction of a band since the last call, in dB (0 or negative).
+    pub fn get_band_reduction_db(&mut self, band: usize) -> f32 {
+        match self.max_reduction_db.get_mut(band) {
+            Some(reduction) => std::mem::replace(reduction, 0.0),
+            None => 0.0,
+        }
+    }
+
+    pub fn process_block(&mut self, left: &mut [f32], right: &mut [f32]) {
+        if !self.enabled {
+            return;
+        }
+
+        let band_count = self.band_count();
+        let any_solo = self.params[..band_count].iter().any(|params| params.solo);
+        let mut bands_l = [0.0; MAX_BANDS];
+        let mut bands_r = [0.0; MAX_BANDS];
+
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            self.split_l.split(*l, &mut bands_l);
+            self.split_r.split(*r, &mut bands_r);
+
+            let mut out_l = 0.0;
+            let mut out_r = 0.0;
+            for band in 0..band_count {
+                let params = self.params[band];
+                let gain = if params.bypass {
+                    1.0
+                } else {
+                    let level = bands_l[band].abs().max(bands_r[band].abs());
+                    let level_db = 20.0 * level.max(1.0e-9).log10();
+                    let target = compressor_gain_db(level_db, params.threshold_db, params.ratio, params.knee_db);
+
+                    // Attack while the reduction deepens, release while it recovers
+                    let current = self.reduction_db[band];
+                    let coeff = if target < current { params.attack_coeff } else { params.release_coeff };
+                    let reduction = coeff * current + (1.0 - coeff) * target;
+                    self.reduction_db[band] = reduction;
+                    self.max_reduction_db[band] = self.max_reduction_db[band].min(reduction);
+
+                    10.0f32.powf(reduction / 20.0) * params.makeup
+                };
+
+                if !any_solo || params.solo {
+                    out_l += bands_l[band] * gain;
+                    out_r += bands_r[band] * gain;
+                }
+            }
+            *l = out_l;
+            *r = out_r;
+        }
+    }
+}
+
+// Crossovers spaced evenly in log-frequency across the configured range.
+fn default_crossovers(bands: usize) -> Vec<f32> {
+    let low = MULTIBAND_CONFIG.lowest_crossover_hz;
+    let high = MULTIBAND_CONFIG.highest_crossover_hz;
+    let count = bands - 1;
+    (0..count)
+        .map(|k| low * (high / low).powf(k as f32 / (count - 1) as f32))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    // Gain in dB of a sine through the compressor, from the RMS once the filters have settled.
+    fn sine_gain_db(compressor: &mut MultibandCompressor, frequency: f32) -> f32 {
+        let omega = 2.0 * std::f64::consts::PI * frequency as f64 / SAMPLE_RATE as f64;
+        let input: Vec<f32> = (0..24000).map(|n| (omega * n as f64).sin() as f32 * 0.5).collect();
+        let (mut left, mut right) = (input.clone(), input.clone());
+        compressor.process_block(&mut left, &mut right);
+        let power = |x: &[f32]| x.iter().map(|v| (v * v) as f64).sum::<f64>();
+        (10.0 * (power(&left[12000..]) / power(&input[12000..])).log10()) as f32
+    }
+
+    #[test]
+    fn crossover_sum_is_flat() {
+        for bands in MIN_BANDS..=MAX_BANDS {
+            let mut compressor = MultibandCompressor::new(SAMPLE_RATE);
+            compressor.set_enabled(true);
+            compressor.set_band_count(bands);
+            for band in 0..bands {
+                compressor.set_band_bypass(band, true);
+            }
+            for frequency in [40.0, 120.0, 500.0, 1000.0, 2500.0, 6000.0, 15000.0] {
+                let gain = sine_gain_db(&mut compressor, frequency);
+                assert!(gain.abs() < 0.05, "{bands} bands at {frequency} Hz: {gain} dB");
+            }
+        }
+    }
+
+    #[test]
+    fn crossover_sum_stays_flat_after_moving_a_crossover() {
+        let mut compressor = MultibandCompressor::new(SAMPLE_RATE);
+        compressor.set_enabled(true);
+        compressor.set_band_count(4);
+        for band in 0..4 {
+            compressor.set_band_bypass(band, true);
+        }
+        compressor.set_crossover(1, 700.0);
+        sine_gain_db(&mut compressor, 700.0); // let the crossover ramp finish
+        for frequency in [300.0, 700.0, 1500.0] {
+            let gain = sine_gain_db(&mut compressor, frequency);
+            assert!(gain.abs() < 0.05, "at {frequency} Hz: {gain} dB");
+        }
+    }
+}
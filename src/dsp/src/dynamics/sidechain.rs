@@ -0,0 +1,67 @@
+use wasm_bindgen::prelude::*;
+use crate::filters::iir::{BiquadFilter, FilterType};
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SidechainMode {
+    Off = 0,
+    HighPass = 1,
+    KWeighting = 2,
+    BandPass = 3,
+}
+
+// ITU-R BS.1770 K-weighting: a +4 dB high shelf (head effect) and the RLB high-pass.
+const K_SHELF_HZ: f32 = 1681.97;
+const K_SHELF_Q: f32 = 0.7072;
+const K_SHELF_DB: f32 = 4.0;
+const K_HIGHPASS_HZ: f32 = 38.14;
+const K_HIGHPASS_Q: f32 = 0.5003;
+
+/// Filter in front of a level detector for one channel, so the detector can ignore
+/// sub-bass (high-pass), follow perceived loudness (K-weighting) or listen to one band.
+#[derive(Clone, Debug)]
+pub struct SidechainFilter {
+    mode: SidechainMode,
+    sections: Vec<BiquadFilter>,
+    sample_rate: f32,
+}
+
+impl SidechainFilter {
+    pub fn new(sample_rate: f32) -> Self {
+        Self {
+            mode: SidechainMode::Off,
+            sections: Vec::new(),
+            sample_rate,
+        }
+    }
+
+    /// `frequency` is the high-pass or band-pass center; K-weighting uses its fixed curve instead.
+    pub fn set_mode(&mut self, mode: SidechainMode, frequency: f32) {
+        self.set_mode_with_q(mode, frequency, 0.707);
+    }
+
+    /// `set_mode` with an explicit Q for the high-pass and band-pass modes.
+    pub fn set_mode_with_q(&mut self, mode: SidechainMode, frequency: f32, q: f32) {
+        let params: &[(FilterType, f32, f32, f32)] = match mode {
+            SidechainMode::Off => &[],
+            SidechainMode::HighPass => &[(FilterType::HighPass, frequency, q, 0.0)],
+            SidechainMode::BandPass => &[(FilterType::BandPass, frequency, q, 0.0)],
+            SidechainMode::KWeighting => &[
+                (FilterType::HighShelf, K_SHELF_HZ, K_SHELF_Q, K_SHELF_DB),
+                (FilterType::HighPass, K_HIGHPASS_HZ, K_HIGHPASS_Q, 0.0),
+            ],
+        };
+        // Keep filter state when only the frequency moves
+        if mode != self.mode || self.sections.len() != params.len() {
+            self.sections = params.iter().map(|_| BiquadFilter::new(self.sample_rate)).collect();
+        }
+        self.mode = mode;
+        for (section, &(filter_type, freq, q, gain)) in self.sections.iter_mut().zip(params) {
+            section.set_params(filter_type, freq, q, gain);
+        }
+    }
+
+    pub fn process(&mut self, sample: f32) -> f32 {
+        self.sections.iter_mut().fold(sample, |x, section| section.process(x))
+    }
+}
@@ -0,0 +1,375 @@
+use wasm_bindgen::prelude::*;
+use rustfft::num_complex::Complex;
+use std::f64::consts::PI;
+use super::iir::{BiquadFilter, FilterType, SectionKind};
+use super::dynamic::DynamicBand;
+use crate::dynamics::compressor::DetectorMode;
+
+// Roll-off of LowPass/HighPass bands, in dB/oct.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FilterSlope {
+    Db6 = 6,
+    Db12 = 12,
+    Db18 = 18,
+    Db24 = 24,
+    Db36 = 36,
+    Db48 = 48,
+}
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SlopeAlignment {
+    Butterworth = 0,
+    LinkwitzRiley = 1,
+}
+
+/// One EQ band: a cascade of biquad sections sharing a frequency.
+/// Only LowPass/HighPass bands use more than one section.
+#[derive(Clone, Debug)]
+pub struct EqBand {
+    sections: Vec<BiquadFilter>,
+    filter_type: FilterType,
+    frequency: f32,
+    q: f32,
+    gain: f32,
+    slope: FilterSlope,
+    alignment: SlopeAlignment,
+    sample_rate: f32,
+    ramp_ms: f32,
+    dynamic: Option<DynamicBand>,
+    fade_out: Option<FadeOut>,
+}
+
+// A removed band on its way out, mixed back to its input over `total` samples.
+// It is flat (and can be dropped) once `remaining` reaches 0.
+#[derive(Clone, Copy, Debug)]
+struct FadeOut {
+    remaining: usize,
+    total: usize,
+}
+
+impl EqBand {
+    pub fn new(sample_rate: f32, ramp_ms: f32) -> Self {
+        let mut section = BiquadFilter::new(sample_rate);
+        section.set_ramp_time(ramp_ms);
+        Self {
+            sections: vec![section],
+            filter_type: FilterType::Peaking,
+            frequency: 1000.0,
+            q: 1.0,
+            gain: 0.0,
+            slope: FilterSlope::Db12,
+            alignment: SlopeAlignment::Butterworth,
+            sample_rate,
+            ramp_ms,
+            dynamic: None,
+            fade_out: None,
+        }
+    }
+
+    pub fn set_ramp_time(&mut self, ramp_ms: f32) {
+        self.ramp_ms = ramp_ms;
+        for section in &mut self.sections {
+            section.set_ramp_time(ramp_ms);
+        }
+    }
+
+    pub fn set_params(&mut self, filter_type: FilterType, frequency: f32, q: f32, gain: f32) {
+        self.filter_type = filter_type;
+        self.frequency = frequency;
+        self.q = q;
+        self.gain = gain;
+        self.update_sections();
+        if let Some(dynamic) = self.dynamic.as_mut() {
+            dynamic.set_band(filter_type, frequency, q);
+        }
+    }
+
+    /// Turns the band into a dynamic band that cuts its gain while the band's region of
+    /// the input is above `threshold_db`. Only affects types with a gain parameter.
+    pub fn set_dynamics(
+        &mut self,
+        threshold_db: f32,
+        ratio: f32,
+        attack_ms: f32,
+        release_ms: f32,
+        detector_mode: DetectorMode,
+    ) {
+        let dynamic = self.dynamic.get_or_insert_with(|| {
+            let mut dynamic = DynamicBand::new(self.sample_rate);
+            dynamic.set_band(self.filter_type, self.frequency, self.q);
+            dynamic
+        });
+        dynamic.set_params(threshold_db, ratio, attack_ms, release_ms, detector_mode);
+    }
+
+    /// Back to a static band.
+    pub fn clear_dynamics(&mut self) {
+        if self.dynamic.take().is_some() {
+            self.set_gain_offset(0.0);
+        }
+    }
+
+    /// Current dynamic gain change in dB, 0 for a static band.
+    pub fn dynamic_gain_db(&self) -> f32 {
+        self.dynamic.as_ref().map_or(0.0, |dynamic| dynamic.offset_db())
+    }
+
+    pub fn set_slope(&mut self, slope: FilterSlope, alignment: SlopeAlignment) {
+        self.slope = slope;
+        self.alignment = alignment;
+        self.update_sections();
+    }
+
+    /// Takes over another band's settings, keeping this band's filter state.
+    pub fn copy_settings(&mut self, other: &EqBand) {
+        self.filter_type = other.filter_type;
+        self.frequency = other.frequency;
+        self.q = other.q;
+        self.gain = other.gain;
+        self.slope = other.slope;
+        self.alignment = other.alignment;
+        self.update_sections();
+        self.dynamic = other.dynamic.clone();
+        if self.dynamic.is_none() {
+            self.set_gain_offset(0.0);
+        }
+    }
+
+    /// Starts taking the band out without a click. Over the ramp time the output is
+    /// crossfaded to the dry signal; gain types also ramp to 0 dB meanwhile, so the mix
+    /// never sums two very different responses. Dynamic bands keep their gain, their
+    /// offset is not ramped. `is_faded_out` tells when the band can be dropped.
+    pub fn fade_out(&mut self) {
+        let total = ((self.ramp_ms.max(0.0) / 1000.0) * self.sample_rate).round() as usize;
+        let has_gain = matches!(
+            self.filter_type,
+            FilterType::LowShelf | FilterType::Peaking | FilterType::HighShelf | FilterType::TiltShelf
+        );
+        if has_gain && self.dynamic.is_none() {
+            self.gain = 0.0;
+            self.update_sections();
+        }
+        self.fade_out = Some(FadeOut { remaining: total, total });
+    }
+
+    pub fn is_faded_out(&self) -> bool {
+        matches!(self.fade_out, Some(fade) if fade.remaining == 0)
+    }
+
+    pub fn process(&mut self, input: f32) -> f32 {
+        if let Some(offset) = self.dynamic.as_mut().and_then(|dynamic| dynamic.process(input)) {
+            self.set_gain_offset(offset);
+        }
+        let mut sample = input;
+        for section in &mut self.sections {
+            sample = section.process(sample);
+        }
+        match self.fade_out.as_mut() {
+            Some(fade) if fade.remaining > 0 => {
+                fade.remaining -= 1;
+                let mix = fade.remaining as f32 / fade.total as f32;
+                input + (sample - input) * mix
+            }
+            Some(_) => input,
+            None => sample,
+        }
+    }
+
+    /// Settled complex response of the whole cascade at `frequency`.
+    pub fn response(&self, frequency: f32) -> Complex<f64> {
+        self.sections
+            .iter()
+            .fold(Complex::new(1.0, 0.0), |acc, section| acc * section.response(frequency))
+    }
+
+    fn update_sections(&mut self) {
+        let (first_order, highpass) = match self.filter_type {
+            FilterType::LowPass => (SectionKind::LowPass1, false),
+            FilterType::HighPass => (SectionKind::HighPass1, true),
+            _ => {
+                self.resize_sections(1);
+                self.sections[0].set_params(self.filter_type, self.frequency, self.q, self.gain);
+                return;
+            }
+        };
+        let second_order = if highpass { FilterType::HighPass } else { FilterType::LowPass };
+
+        // A plain 12 dB/oct Butterworth band keeps the user's Q, as before slopes existed.
+        if self.slope == FilterSlope::Db12 && self.alignment == SlopeAlignment::Butterworth {
+            self.resize_sections(1);
+            self.sections[0].set_params(second_order, self.frequency, self.q, self.gain);
+            return;
+        }
+
+        let stages = section_plan(self.slope, self.alignment);
+        self.resize_sections(stages.len());
+        for (section, q) in self.sections.iter_mut().zip(stages) {
+            match q {
+                Some(q) => section.set_params(second_order, self.frequency, q, self.gain),
+                None => section.set_section(first_order, self.frequency, 0.707, self.gain),
+            }
+        }
+    }
+
+    fn set_gain_offset(&mut self, offset: f32) {
+        for section in &mut self.sections {
+            section.set_gain_offset(offset);
+        }
+    }
+
+    // New sections start flat; kept sections keep their state.
+    fn resize_sections(&mut self, count: usize) {
+        self.sections.truncate(count);
+        while self.sections.len() < count {
+            let mut section = BiquadFilter::new(self.sample_rate);
+            section.set_ramp_time(self.ramp_ms);
+            self.sections.push(section);
+        }
+    }
+}
+
+// Section Qs for a slope: `None` is a first-order section, `Some(q)` a second-order one.
+// Linkwitz-Riley is two identical Butterworth cascades of half the order, so it only
+// exists for even orders; 6 and 18 dB/oct fall back to Butterworth.
+fn section_plan(slope: FilterSlope, alignment: SlopeAlignment) -> Vec<Option<f32>> {
+    let order = slope as usize / 6;
+    if alignment == SlopeAlignment::LinkwitzRiley && order.is_multiple_of(2) {
+        let half = butterworth_qs(order / 2);
+        half.iter().chain(half.iter()).copied().collect()
+    } else {
+        butterworth_qs(order)
+    }
+}
+
+fn butterworth_qs(order: usize) -> Vec<Option<f32>> {
+    let mut qs = Vec::with_capacity(order / 2 + 1);
+    if !order.is_multiple_of(2) {
+        qs.push(None);
+    }
+    // Pole pair k sits at angle theta_k from the negative real axis; Q = 1 / (2 cos theta_k)
+    for k in 0..order / 2 {
+        let theta = if order.is_multiple_of(2) {
+            PI * (2 * k + 1) as f64 / (2 * order) as f64
+        } else {
+            PI * (k + 1) as f64 / order as f64
+        };
+        qs.push(Some((1.0 / (2.0 * theta.cos())) as f32));
+    }
+    qs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+    const RAMP_MS: f32 = 20.0;
+    // 1 kHz at 48 kHz: one period every 48 samples
+    const PERIOD: usize = 48;
+
+    fn sine(n: usize) -> f32 {
+        (2.0 * std::f32::consts::PI * n as f32 / PERIOD as f32).sin()
+    }
+
+    // Peak level of each period of a 1 kHz sine while `band` fades out, after a settled second.
+    fn fade_out_levels(band: &mut EqBand) -> Vec<f32> {
+        let settle = SAMPLE_RATE as usize;
+        for n in 0..settle {
+            band.process(sine(n));
+        }
+        band.fade_out();
+        let mut levels = Vec::new();
+        let mut n = settle;
+        while !band.is_faded_out() {
+            let level = (0..PERIOD).fold(0.0f32, |peak, _| {
+                n += 1;
+                peak.max(band.process(sine(n - 1)).abs())
+            });
+            levels.push(level);
+        }
+        // Flat from here on
+        for _ in 0..PERIOD {
+            assert_eq!(band.process(sine(n)), sine(n));
+            n += 1;
+        }
+        levels
+    }
+
+    #[test]
+    fn removed_peaking_band_ramps_to_flat() {
+        let mut band = EqBand::new(SAMPLE_RATE, RAMP_MS);
+        band.set_params(FilterType::Peaking, 1000.0, 1.0, 12.0);
+        let levels = fade_out_levels(&mut band);
+        assert_eq!(levels.len(), (RAMP_MS / 1000.0 * SAMPLE_RATE) as usize / PERIOD);
+        assert!(levels[0] > 3.0, "{levels:?}");
+        // Falls steadily towards unity instead of jumping there
+        assert!(levels.windows(2).all(|pair| pair[1] <= pair[0] + 1.0e-3), "{levels:?}");
+        assert!(levels.windows(2).all(|pair| pair[0] - pair[1] < 0.5), "{levels:?}");
+    }
+
+    #[test]
+    fn removed_pass_band_crossfades_to_dry() {
+        let mut band = EqBand::new(SAMPLE_RATE, RAMP_MS);
+        band.set_params(FilterType::LowPass, 200.0, 0.707, 0.0);
+        band.set_slope(FilterSlope::Db24, SlopeAlignment::Butterworth);
+        let levels = fade_out_levels(&mut band);
+        assert!(levels[0] < 0.1, "{levels:?}");
+        assert!(levels.windows(2).all(|pair| pair[1] >= pair[0] - 1.0e-3), "{levels:?}");
+        assert!(levels.windows(2).all(|pair| pair[1] - pair[0] < 0.1), "{levels:?}");
+    }
+
+    const SLOPES: [FilterSlope; 6] = [
+        FilterSlope::Db6,
+        FilterSlope::Db12,
+        FilterSlope::Db18,
+        FilterSlope::Db24,
+        FilterSlope::Db36,
+        FilterSlope::Db48,
+    ];
+
+    fn pass_band(filter_type: FilterType, slope: FilterSlope, alignment: SlopeAlignment) -> EqBand {
+        let mut band = EqBand::new(SAMPLE_RATE, 0.0);
+        band.set_params(filter_type, 1000.0, std::f32::consts::FRAC_1_SQRT_2, 0.0);
+        band.set_slope(slope, alignment);
+        band
+    }
+
+    #[test]
+    fn slopes_use_the_expected_sections() {
+        // Linkwitz-Riley only exists for even orders; 6 and 18 dB/oct fall back to Butterworth
+        let butterworth = [1, 1, 2, 2, 3, 4];
+        let linkwitz_riley = [1, 2, 2, 2, 4, 4];
+        for (i, &slope) in SLOPES.iter().enumerate() {
+            for filter_type in [FilterType::LowPass, FilterType::HighPass] {
+                let band = pass_band(filter_type, slope, SlopeAlignment::Butterworth);
+                assert_eq!(band.sections.len(), butterworth[i], "{filter_type:?} {slope:?} Butterworth");
+                let band = pass_band(filter_type, slope, SlopeAlignment::LinkwitzRiley);
+                assert_eq!(band.sections.len(), linkwitz_riley[i], "{filter_type:?} {slope:?} LR");
+            }
+            // Slopes only apply to pass bands
+            let mut band = pass_band(FilterType::Peaking, slope, SlopeAlignment::LinkwitzRiley);
+            band.set_params(FilterType::Peaking, 1000.0, 1.0, 6.0);
+            assert_eq!(band.sections.len(), 1);
+        }
+    }
+
+    #[test]
+    fn corner_is_3_db_down_for_butterworth_and_6_db_for_linkwitz_riley() {
+        for slope in SLOPES {
+            for filter_type in [FilterType::LowPass, FilterType::HighPass] {
+                for alignment in [SlopeAlignment::Butterworth, SlopeAlignment::LinkwitzRiley] {
+                    let band = pass_band(filter_type, slope, alignment);
+                    let odd = matches!(slope, FilterSlope::Db6 | FilterSlope::Db18);
+                    let expected_db = if alignment == SlopeAlignment::LinkwitzRiley && !odd { -6.02 } else { -3.01 };
+                    let corner_db = 20.0 * band.response(1000.0).norm().log10() as f32;
+                    assert!(
+                        (corner_db - expected_db).abs() < 0.05,
+                        "{filter_type:?} {slope:?} {alignment:?}: {corner_db} dB"
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use wasm_bindgen::prelude::*;
+use rustfft::{Fft, FftPlanner, num_complex::Complex};
+use rustfft::num_traits::Zero;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FirWindow {
+    Rectangular = 0,
+    Hann = 1,
+    Hamming = 2,
+    Blackman = 3,
+}
+
+// Magnitudes below this (-120 dB) are clamped before taking the log for the cepstrum.
+const MAGNITUDE_FLOOR: f64 = 1.0e-6;
+
+// Design FFT size relative to the tap count; more padding means less cepstral aliasing.
+const DESIGN_OVERSAMPLING: usize = 8;
+
+/// Designs FIR taps from a target curve given as (frequency Hz, gain dB) points.
+/// The curve is interpolated linearly in log-frequency and held flat beyond its ends.
+///
+/// `min_phase` blends the phase: 0.0 is linear phase (delay of (num_taps - 1) / 2),
+/// 1.0 is minimum phase, anything in between is mixed phase.
+#[wasm_bindgen]
+pub fn design_fir(
+    frequencies: &[f32],
+    gains_db: &[f32],
+    sample_rate: f32,
+    num_taps: usize,
+    min_phase: f32,
+    window: FirWindow,
+) -> Vec<f32> {
+    let num_taps = num_taps.max(1);
+    let fft_size = (num_taps * DESIGN_OVERSAMPLING).next_power_of_two();
+    let bin_size = sample_rate / fft_size as f32;
+    let magnitudes: Vec<f32> = (0..=fft_size / 2)
+        .map(|k| {
+            let db = interpolate_log(frequencies, gains_db, k as f32 * bin_size);
+            10.0f32.powf(db / 20.0)
+        })
+        .collect();
+    fir_from_bins(&magnitudes, fft_size, num_taps, min_phase, window)
+}
+
+/// Designs FIR taps from magnitudes sampled on `fft_size / 2 + 1` evenly spaced bins
+/// (DC to Nyquist). `num_taps` should be odd for linear phase so the delay is whole.
+pub fn fir_from_bins(
+    magnitudes: &[f32],
+    fft_size: usize,
+    num_taps: usize,
+    min_phase: f32,
+    window: FirWindow,
+) -> Vec<f32> {
+    let mut taps = vec![0.0; num_taps.min(fft_size - 1).max(1)];
+    FirDesigner::new(fft_size).design(magnitudes, &mut taps, min_phase, window);
+    taps
+}
+
+/// `fir_from_bins` with its FFT plans and work buffers kept between designs, so a
+/// redesign on the audio thread neither plans nor allocates.
+pub struct FirDesigner {
+    fft_size: usize,
+    fft: Arc<dyn Fft<f64>>,
+    ifft: Arc<dyn Fft<f64>>,
+    spectrum: Vec<Complex<f64>>,
+    min_phase_spectrum: Vec<f64>,
+    scratch: Vec<Complex<f64>>,
+}
+
+impl FirDesigner {
+    pub fn new(fft_size: usize) -> Self {
+        let mut planner = FftPlanner::<f64>::new();
+        let fft = planner.plan_fft_forward(fft_size);
+        let ifft = planner.plan_fft_inverse(fft_size);
+        let scratch_len = fft.get_inplace_scratch_len().max(ifft.get_inplace_scratch_len());
+        Self {
+            fft_size,
+            fft,
+            ifft,
+            spectrum: vec![Complex::zero(); fft_size],
+            min_phase_spectrum: vec![0.0; fft_size / 2 + 1],
+            scratch: vec![Complex::zero(); scratch_len],
+        }
+    }
+
+    /// Fills `taps` (at most `fft_size - 1` of them) from `fft_size / 2 + 1` bin magnitudes,
+    /// see `fir_from_bins`.
+    pub fn design(&mut self, magnitudes: &[f32], taps: &mut [f32], min_phase: f32, window: FirWindow) {
+        let fft_size = self.fft_size;
+        let half = fft_size / 2;
+        let num_taps = taps.len().min(fft_size - 1).max(1);
+        let blend = min_phase.clamp(0.0, 1.0) as f64;
+        let delay = (num_taps - 1) as f64 / 2.0;
+
+        let magnitude = |k: usize| magnitudes.get(k).copied().unwrap_or(0.0) as f64;
+
+        // Minimum phase from the folded real cepstrum of log|H|
+        self.min_phase_spectrum.fill(0.0);
+        if blend > 0.0 {
+            let cepstrum = &mut self.spectrum;
+            for (k, c) in cepstrum.iter_mut().enumerate() {
+                let k = if k <= half { k } else { fft_size - k };
+                *c = Complex::new(magnitude(k).max(MAGNITUDE_FLOOR).ln(), 0.0);
+            }
+            self.ifft.process_with_scratch(cepstrum, &mut self.scratch);
+            for (n, c) in cepstrum.iter_mut().enumerate() {
+                let fold = match n {
+                    0 => 1.0,
+                    n if n < half => 2.0,
+                    n if n == half => 1.0,
+                    _ => 0.0,
+                };
+                *c = Complex::new(c.re * fold / fft_size as f64, 0.0);
+            }
+            self.fft.process_with_scratch(cepstrum, &mut self.scratch);
+            for (phase, c) in self.min_phase_spectrum.iter_mut().zip(cepstrum.iter()) {
+                *phase = c.im;
+            }
+        }
+
+        // Blend the phases and build a conjugate-symmetric spectrum for a real impulse response
+        let spectrum = &mut self.spectrum;
+        for k in 0..=half {
+            let omega = 2.0 * PI * k as f64 / fft_size as f64;
+            let phase = blend * self.min_phase_spectrum[k] - (1.0 - blend) * omega * delay;
+            spectrum[k] = Complex::from_polar(magnitude(k), phase);
+            if k > 0 && k < half {
+                spectrum[fft_size - k] = spectrum[k].conj();
+            }
+        }
+        self.ifft.process_with_scratch(spectrum, &mut self.scratch);
+
+        // The window peaks where the impulse does: centred for linear phase, at the first tap for
+        // minimum phase. Each side of the peak gets its own half of the window.
+        let peak = (1.0 - blend) * delay;
+        let tail = (num_taps - 1) as f64 - peak;
+        for (i, tap) in taps[..num_taps].iter_mut().enumerate() {
+            let n = i as f64;
+            let x = if n < peak {
+                0.5 * n / peak
+            } else if tail > 0.0 {
+                0.5 + 0.5 * (n - peak) / tail
+            } else {
+                0.5
+            };
+            *tap = (spectrum[i].re / fft_size as f64) as f32 * window_shape(window, x);
+        }
+    }
+}
+
+/// Windowed-sinc lowpass with a Kaiser window and unity DC gain. `beta` trades transition
+/// width for stopband depth (8.0 gives about 80 dB).
+pub fn kaiser_lowpass_taps(num_taps: usize, cutoff: f32, beta: f32) -> Vec<f32> {
+    let center = (num_taps as f64 - 1.0) / 2.0;
+    let cutoff = cutoff as f64;
+    let beta = beta as f64;
+    let mut taps: Vec<f32> = (0..num_taps)
+        .map(|i| {
+            let t = i as f64 - center;
+            let sinc = if t == 0.0 { 2.0 * cutoff } else { (2.0 * PI * cutoff * t).sin() / (PI * t) };
+            let x = if center > 0.0 { t / center } else { 0.0 };
+            let window = bessel_i0(beta * (1.0 - x * x).max(0.0).sqrt()) / bessel_i0(beta);
+            (sinc * window) as f32
+        })
+        .collect();
+    let sum: f32 = taps.iter().sum();
+    for tap in &mut taps {
+        *tap /= sum;
+    }
+    taps
+}
+
+// Zeroth-order modified Bessel function of the first kind, by its power series.
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half = x / 2.0;
+    for k in 1..50 {
+        term *= (half / k as f64) * (half / k as f64);
+        sum += term;
+        if term < sum * 1.0e-12 {
+            break;
+        }
+    }
+    sum
+}
+
+// Window evaluated at `x` in [0, 1] across its length (peak at 0.5).
+fn window_shape(window: FirWindow, x: f64) -> f32 {
+    let x = 2.0 * PI * x;
+    let w = match window {
+        FirWindow::Rectangular => 1.0,
+        FirWindow::Hann => 0.5 - 0.5 * x.cos(),
+        FirWindow::Hamming => 0.54 - 0.46 * x.cos(),
+        FirWindow::Blackman => 0.42 - 0.5 * x.cos() + 0.08 * (2.0 * x).cos(),
+    };
+    w as f32
+}
+
+fn interpolate_log(frequencies: &[f32], gains_db: &[f32], frequency: f32) -> f32 {
+    let points = frequencies.len().min(gains_db.len());
+    if points == 0 {
+        return 0.0;
+    }
+    if frequency <= frequencies[0] {
+        return gains_db[0];
+    }
+    if frequency >= frequencies[points - 1] {
+        return gains_db[points - 1];
+    }
+    let upper = frequencies[..points].partition_point(|&f| f < frequency);
+    let lower = upper - 1;
+    let (f0, f1) = (frequencies[lower].max(1.0e-3), frequencies[upper].max(1.0e-3));
+    if f1 <= f0 {
+        return gains_db[upper];
+    }
+    let t = (frequency / f0).ln() / (f1 / f0).ln();
+    gains_db[lower] + (gains_db[upper] - gains_db[lower]) * t
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flat_target_gives_a_unit_impulse() {
+        let num_taps = 255;
+        // Linear phase peaks in the middle, minimum phase at the first tap
+        for (min_phase, peak) in [(0.0, num_taps / 2), (1.0, 0)] {
+            for window in [FirWindow::Rectangular, FirWindow::Blackman] {
+                let taps = design_fir(&[20.0, 20000.0], &[0.0, 0.0], 48000.0, num_taps, min_phase, window);
+                assert_eq!(taps.len(), num_taps);
+                for (n, &tap) in taps.iter().enumerate() {
+                    let expected = if n == peak { 1.0 } else { 0.0 };
+                    assert!((tap - expected).abs() < 1.0e-4, "min_phase {min_phase}, {window:?}: tap {n} = {tap}");
+                }
+            }
+        }
+    }
+}
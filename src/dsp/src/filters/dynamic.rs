@@ -0,0 +1,108 @@
+use super::iir::{BiquadFilter, FilterType};
+use crate::config::DYNAMIC_EQ_CONFIG;
+use crate::dynamics::compressor::{time_coeff, DetectorMode};
+
+// The band gain follows the envelope in steps of UPDATE_INTERVAL samples.
+const UPDATE_INTERVAL: usize = 16;
+
+/// Level-dependent gain for one EQ band. A sidechain filter isolates the band's
+/// region of the input; while its level is above the threshold the band is cut
+/// by `(level - threshold) * (1 - 1 / ratio)` dB, up to `max_cut_db`.
+#[derive(Clone, Debug)]
+pub struct DynamicBand {
+    sidechain: BiquadFilter,
+    threshold_db: f32,
+    ratio: f32,
+    attack_coeff: f32,
+    release_coeff: f32,
+    detector_mode: DetectorMode,
+    rms_coeff: f32,
+    sample_rate: f32,
+
+    // State
+    rms: f32,
+    peak: f32,
+    offset_db: f32,
+    counter: usize,
+}
+
+impl DynamicBand {
+    pub fn new(sample_rate: f32) -> Self {
+        let rms_time_s = DYNAMIC_EQ_CONFIG.rms_time_ms / 1000.0;
+        Self {
+            sidechain: BiquadFilter::new(sample_rate),
+            threshold_db: 0.0,
+            ratio: 1.0,
+            attack_coeff: 0.0,
+            release_coeff: 0.0,
+            detector_mode: DetectorMode::Peak,
+            rms_coeff: (-1.0 / (rms_time_s * sample_rate)).exp(),
+            sample_rate,
+            rms: 0.0,
+            peak: 0.0,
+            offset_db: 0.0,
+            counter: 0,
+        }
+    }
+
+    pub fn set_params(
+        &mut self,
+        threshold_db: f32,
+        ratio: f32,
+        attack_ms: f32,
+        release_ms: f32,
+        detector_mode: DetectorMode,
+    ) {
+        self.threshold_db = threshold_db;
+        self.ratio = ratio.max(1.0);
+        self.attack_coeff = time_coeff(attack_ms, self.sample_rate);
+        self.release_coeff = time_coeff(release_ms, self.sample_rate);
+        self.detector_mode = detector_mode;
+    }
+
+    /// Points the sidechain at the band: shelves listen below/above their corner,
+    /// every other type listens to a band-pass around its frequency.
+    pub fn set_band(&mut self, filter_type: FilterType, frequency: f32, q: f32) {
+        match filter_type {
+            FilterType::LowShelf => self.sidechain.set_params(FilterType::LowPass, frequency, 0.707, 0.0),
+            FilterType::HighShelf => self.sidechain.set_params(FilterType::HighPass, frequency, 0.707, 0.0),
+            _ => self.sidechain.set_params(FilterType::BandPass, frequency, q, 0.0),
+        }
+    }
+
+    /// Current gain change in dB (0 or negative).
+    pub fn offset_db(&self) -> f32 {
+        self.offset_db
+    }
+
+    /// Feeds one band input sample to the detector. Returns the new gain offset
+    /// once every UPDATE_INTERVAL samples.
+    pub fn process(&mut self, input: f32) -> Option<f32> {
+        let band = self.sidechain.process(input);
+        let level = match self.detector_mode {
+            DetectorMode::Peak => band.abs(),
+            DetectorMode::Rms => {
+                self.rms = self.rms_coeff * self.rms + (1.0 - self.rms_coeff) * (band * band);
+                self.rms.sqrt()
+            }
+        };
+        // Detector envelope tracks in the linear domain, the gain computer runs on blocks
+        let coeff = if level > self.peak { self.attack_coeff } else { self.release_coeff };
+        self.peak = coeff * self.peak + (1.0 - coeff) * level;
+
+        self.counter += 1;
+        if self.counter < UPDATE_INTERVAL {
+            return None;
+        }
+        self.counter = 0;
+
+        let level_db = 20.0 * self.peak.max(1.0e-9).log10();
+        let over = level_db - self.threshold_db;
+        self.offset_db = if over > 0.0 {
+            -(over * (1.0 - 1.0 / self.ratio)).min(DYNAMIC_EQ_CONFIG.max_cut_db)
+        } else {
+            0.0
+        };
+        Some(self.offset_db)
+    }
+}
@@ -1,78 +1,227 @@
 use rustfft::{Fft, FftPlanner, num_complex::Complex};
+use rustfft::num_traits::Zero;
 use std::sync::Arc;
 
+// Partition length of the FFT convolver. Also the extra latency it adds on top of the taps' own delay.
+const BLOCK_SIZE: usize = 128;
+
+// Uniformly partitioned overlap-save convolution.
+// Input is buffered into blocks of BLOCK_SIZE, each block is transformed once with a 2 * BLOCK_SIZE FFT,
+// and the output block is the sum of the last N input spectra times the N tap partitions.
 pub struct FirFilter {
-    taps: Vec<f32>,
-    buffer: Vec<f32>,
-    position: usize,
-    fft: Option<Arc<dyn Fft<f32>>>,
-    planner: FftPlanner<f32>,
+    partitions: Vec<Vec<Complex<f32>>>,
+    // Previous partitions, crossfaded out over one block after set_taps
+    fading_partitions: Option<Vec<Vec<Complex<f32>>>>,
+    // Buffers of the last faded-out partitions, reused by the next set_taps
+    spare_partitions: Vec<Vec<Complex<f32>>>,
+
+    // Frequency-domain delay line (most recent input spectrum at `fdl_pos`)
+    fdl: Vec<Vec<Complex<f32>>>,
+    fdl_pos: usize,
+
+    // Time-domain buffers
+    buffer: Vec<f32>, // last two input blocks
+    position: usize,  // fill position within the current block
+    output: Vec<f32>,
+
+    fft: Arc<dyn Fft<f32>>,
+    ifft: Arc<dyn Fft<f32>>,
+    spectrum: Vec<Complex<f32>>,
+    accum: Vec<Complex<f32>>,
+    fade_accum: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
 }
 
 impl FirFilter {
-    pub fn new(taps: Vec<f32>) -> Self {
-        let len = taps.len();
-        Self {
-            taps,
-            buffer: vec![0.0; len],
+    pub fn new(taps: &[f32]) -> Self {
+        let fft_size = 2 * BLOCK_SIZE;
+        let mut planner = FftPlanner::new();
+        let fft = planner.plan_fft_forward(fft_size);
+        let ifft = planner.plan_fft_inverse(fft_size);
+        let scratch_len = fft.get_inplace_scratch_len().max(ifft.get_inplace_scratch_len());
+
+        let mut filter = Self {
+            partitions: Vec::new(),
+            fading_partitions: None,
+            spare_partitions: Vec::new(),
+            fdl: Vec::new(),
+            fdl_pos: 0,
+            buffer: vec![0.0; fft_size],
             position: 0,
-            fft: None,
-            planner: FftPlanner::new(),
-        }
+            output: vec![0.0; BLOCK_SIZE],
+            fft,
+            ifft,
+            spectrum: vec![Complex::zero(); fft_size],
+            accum: vec![Complex::zero(); fft_size],
+            fade_accum: vec![Complex::zero(); fft_size],
+            scratch: vec![Complex::zero(); scratch_len],
+        };
+        let mut partitions = Vec::new();
+        filter.fill_partitions(&mut partitions, taps);
+        filter.fdl = vec![vec![Complex::zero(); fft_size]; partitions.len()];
+        filter.spare_partitions = partitions.clone();
+        filter.partitions = partitions;
+        filter
     }
 
-    pub fn process(&mut self, input: f32) -> f32 {
-        let len = self.taps.len();
-        self.buffer[self.position] = input;
-        
-        let mut output = 0.0;
-        let mut index = self.position;
-        
-        // Convolution
-        for i in 0..len {
-            output += self.buffer[index] * self.taps[i];
-            if index == 0 {
-                index = len - 1;
-            } else {
-                index -= 1;
+    /// Replaces the impulse response. The old response is crossfaded out over one block,
+    /// and input history is kept so the change does not restart the convolution.
+    /// Does not allocate while the tap count stays the same.
+    pub fn set_taps(&mut self, taps: &[f32]) {
+        // A fade that has not started yet is dropped, its buffers take the new taps
+        let mut partitions = self
+            .fading_partitions
+            .take()
+            .unwrap_or_else(|| std::mem::take(&mut self.spare_partitions));
+        self.fill_partitions(&mut partitions, taps);
+        let fft_size = 2 * BLOCK_SIZE;
+        if partitions.len() > self.fdl.len() {
+            // Grow the delay line behind the newest spectrum, keeping its order intact
+            let extra = partitions.len() - self.fdl.len();
+            let insert_at = self.fdl_pos + 1;
+            for _ in 0..extra {
+                self.fdl.insert(insert_at, vec![Complex::zero(); fft_size]);
             }
         }
-        
+        self.fading_partitions = Some(std::mem::replace(&mut self.partitions, partitions));
+    }
+
+    /// Delay in samples introduced by block buffering (the taps' own delay is not included).
+    pub fn block_latency(&self) -> usize {
+        BLOCK_SIZE
+    }
+
+    pub fn process(&mut self, input: f32) -> f32 {
+        self.buffer[BLOCK_SIZE + self.position] = input;
+        let output = self.output[self.position];
+
         self.position += 1;
-        if self.position >= len {
+        if self.position >= BLOCK_SIZE {
+            self.process_partition();
             self.position = 0;
         }
-        
+
         output
     }
-    
-    // Create linear phase windowed sinc filter
-    pub fn create_lowpass(cutoff: f32, sample_rate: f32, num_taps: usize) -> Self {
-        let mut taps = vec![0.0; num_taps];
-        let center = (num_taps - 1) as f32 / 2.0;
-        let omega = 2.0 * std::f32::consts::PI * cutoff / sample_rate;
-        
-        for i in 0..num_taps {
-            let n = i as f32 - center;
-            // Sinc function
-            if n == 0.0 {
-                taps[i] = omega / std::f32::consts::PI;
-            } else {
-                taps[i] = (omega * n).sin() / (std::f32::consts::PI * n);
+
+    fn process_partition(&mut self) {
+        let fft_size = 2 * BLOCK_SIZE;
+
+        // Transform the last two input blocks into the newest delay-line slot
+        self.fdl_pos = (self.fdl_pos + 1) % self.fdl.len();
+        for (bin, &sample) in self.spectrum.iter_mut().zip(self.buffer.iter()) {
+            *bin = Complex::new(sample, 0.0);
+        }
+        self.fft.process_with_scratch(&mut self.spectrum, &mut self.scratch);
+        self.fdl[self.fdl_pos].copy_from_slice(&self.spectrum);
+
+        multiply_accumulate(&self.fdl, self.fdl_pos, &self.partitions, &mut self.accum);
+        self.ifft.process_with_scratch(&mut self.accum, &mut self.scratch);
+
+        // Overlap-save: only the second half of the circular result is valid
+        let norm = 1.0 / fft_size as f32;
+        if let Some(fading) = self.fading_partitions.take() {
+            multiply_accumulate(&self.fdl, self.fdl_pos, &fading, &mut self.fade_accum);
+            self.ifft.process_with_scratch(&mut self.fade_accum, &mut self.scratch);
+            for i in 0..BLOCK_SIZE {
+                let t = (i as f32 + 0.5) / BLOCK_SIZE as f32;
+                let new = self.accum[BLOCK_SIZE + i].re;
+                let old = self.fade_accum[BLOCK_SIZE + i].re;
+                self.output[i] = (old + (new - old) * t) * norm;
+            }
+            self.spare_partitions = fading;
+        } else {
+            for (out, bin) in self.output.iter_mut().zip(&self.accum[BLOCK_SIZE..]) {
+                *out = bin.re * norm;
+            }
+        }
+
+        // Slide the input window by one block
+        self.buffer.copy_within(BLOCK_SIZE.., 0);
+    }
+
+    // Transforms `taps` into `partitions`, resizing it to the partition count. Buffers already
+    // there are reused, so same-length tap sets do not allocate.
+    fn fill_partitions(&mut self, partitions: &mut Vec<Vec<Complex<f32>>>, taps: &[f32]) {
+        let fft_size = 2 * BLOCK_SIZE;
+        let count = taps.len().div_ceil(BLOCK_SIZE).max(1);
+        partitions.resize_with(count, || vec![Complex::zero(); fft_size]);
+        for (p, spectrum) in partitions.iter_mut().enumerate() {
+            spectrum.fill(Complex::zero());
+            let start = (p * BLOCK_SIZE).min(taps.len());
+            let end = ((p + 1) * BLOCK_SIZE).min(taps.len());
+            for (bin, &tap) in spectrum.iter_mut().zip(&taps[start..end]) {
+                *bin = Complex::new(tap, 0.0);
             }
-            
-            // Blackman window
-            let window = 0.42 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / (num_taps - 1) as f32).cos() 
-                       + 0.08 * (4.0 * std::f32::consts::PI * i as f32 / (num_taps - 1) as f32).cos();
-            taps[i] *= window;
+            self.fft.process_with_scratch(spectrum, &mut self.scratch);
+        }
+    }
+}
+
+fn multiply_accumulate(
+    fdl: &[Vec<Complex<f32>>],
+    newest: usize,
+    partitions: &[Vec<Complex<f32>>],
+    accum: &mut [Complex<f32>],
+) {
+    accum.fill(Complex::zero());
+    // Partition p pairs with the input spectrum from p blocks ago
+    for (p, partition) in partitions.iter().enumerate() {
+        let slot = &fdl[(newest + fdl.len() - p) % fdl.len()];
+        for ((acc, x), h) in accum.iter_mut().zip(slot).zip(partition) {
+            *acc += x * h;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn random_taps(len: usize) -> Vec<f32> {
+        let mut seed = 0x2545_f491u32;
+        (0..len)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                seed as f32 / u32::MAX as f32 - 0.5
+            })
+            .collect()
+    }
+
+    fn impulse_response(filter: &mut FirFilter, len: usize) -> Vec<f32> {
+        (0..len).map(|n| filter.process(if n == 0 { 1.0 } else { 0.0 })).collect()
+    }
+
+    #[test]
+    fn impulse_gives_the_taps_after_the_block_latency() {
+        // Several partitions, the last one partly filled
+        let taps = random_taps(5 * BLOCK_SIZE + 37);
+        let mut filter = FirFilter::new(&taps);
+        let latency = filter.block_latency();
+        let response = impulse_response(&mut filter, latency + taps.len() + BLOCK_SIZE);
+
+        assert!(response[..latency].iter().all(|&x| x.abs() < 1.0e-6));
+        for (n, (&out, &tap)) in response[latency..].iter().zip(&taps).enumerate() {
+            assert!((out - tap).abs() < 1.0e-5, "tap {n}: {out} vs {tap}");
+        }
+        assert!(response[latency + taps.len()..].iter().all(|&x| x.abs() < 1.0e-5));
+    }
+
+    #[test]
+    fn set_taps_switches_the_response() {
+        let mut filter = FirFilter::new(&random_taps(3 * BLOCK_SIZE));
+        let taps: Vec<f32> = random_taps(3 * BLOCK_SIZE).iter().map(|x| -0.5 * x).collect();
+        filter.set_taps(&taps);
+        // Past the one-block crossfade and the old response's tail
+        for _ in 0..8 * BLOCK_SIZE {
+            filter.process(0.0);
         }
-        
-        // Normalize gain
-        let sum: f32 = taps.iter().sum();
-        for i in 0..num_taps {
-            taps[i] /= sum;
+        let latency = filter.block_latency();
+        let response = impulse_response(&mut filter, latency + taps.len());
+        for (&out, &tap) in response[latency..].iter().zip(&taps) {
+            assert!((out - tap).abs() < 1.0e-5);
         }
-        
-        Self::new(taps)
     }
 }
@@ -1,19 +1,37 @@
+use wasm_bindgen::prelude::*;
+use rustfft::num_complex::Complex;
 use std::f32::consts::PI;
 
-#[derive(Clone, Copy, Debug)]
+// Ids 0-2 match the original LowShelf/Peaking/HighShelf type ids used by the worklet.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum FilterType {
-    LowPass,
-    HighPass,
-    BandPass,
-    Notch,
-    Peaking,
-    LowShelf,
-    HighShelf,
+    LowShelf = 0,
+    Peaking = 1,
+    HighShelf = 2,
+    LowPass = 3,
+    HighPass = 4,
+    BandPass = 5,
+    Notch = 6,
+    AllPass = 7,
+    TiltShelf = 8,
 }
 
+// What a section computes: one of the public filter types, or a first-order (6 dB/oct)
+// lowpass/highpass that odd-order slope cascades are built from. Q is unused for those.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SectionKind {
+    Biquad(FilterType),
+    LowPass1,
+    HighPass1,
+}
+
+// Coefficients are recomputed every SMOOTHING_INTERVAL samples while a parameter ramp runs.
+const SMOOTHING_INTERVAL: usize = 16;
+
 #[derive(Clone, Debug)]
 pub struct BiquadFilter {
-    filter_type: FilterType,
+    kind: SectionKind,
     frequency: f32,
     q: f32,
     gain: f32,
@@ -26,12 +44,23 @@ pub struct BiquadFilter {
     // State
     x1: f64, x2: f64,
     y1: f64, y2: f64,
+
+    // Parameter ramp
+    ramp_samples: usize,
+    ramp_steps_left: usize,
+    ramp_counter: usize,
+    target_frequency: f32,
+    target_q: f32,
+    target_gain: f32,
+
+    // Dynamic EQ gain on top of `gain`, applied without a ramp
+    gain_offset: f32,
 }
 
 impl BiquadFilter {
     pub fn new(sample_rate: f32) -> Self {
         Self {
-            filter_type: FilterType::Peaking,
+            kind: SectionKind::Biquad(FilterType::Peaking),
             frequency: 1000.0,
             q: 1.0,
             gain: 0.0,
@@ -40,104 +69,113 @@ impl BiquadFilter {
             a1: 0.0, a2: 0.0,
             x1: 0.0, x2: 0.0,
             y1: 0.0, y2: 0.0,
+            ramp_samples: 0,
+            ramp_steps_left: 0,
+            ramp_counter: 0,
+            target_frequency: 1000.0,
+            target_q: 1.0,
+            target_gain: 0.0,
+            gain_offset: 0.0,
         }
     }
 
+    /// Time over which frequency, Q and gain changes are ramped. 0 applies changes instantly.
+    pub fn set_ramp_time(&mut self, ramp_ms: f32) {
+        self.ramp_samples = ((ramp_ms.max(0.0) / 1000.0) * self.sample_rate).round() as usize;
+    }
+
     pub fn set_params(&mut self, filter_type: FilterType, frequency: f32, q: f32, gain: f32) {
-        self.filter_type = filter_type;
-        self.frequency = frequency;
-        self.q = q.max(0.01); // Prevent division by zero
-        self.gain = gain;
+        self.set_section(SectionKind::Biquad(filter_type), frequency, q, gain);
+    }
+
+    pub fn set_section(&mut self, kind: SectionKind, frequency: f32, q: f32, gain: f32) {
+        let q = q.max(0.01); // Prevent division by zero
+        let frequency = frequency.max(1.0); // Ramp runs in log-frequency
+        let steps = self.ramp_samples / SMOOTHING_INTERVAL;
+
+        // A type change has no meaningful in-between, so it is applied directly.
+        if steps == 0 || kind != self.kind {
+            self.kind = kind;
+            self.frequency = frequency;
+            self.q = q;
+            self.gain = gain;
+            self.target_frequency = frequency;
+            self.target_q = q;
+            self.target_gain = gain;
+            self.ramp_steps_left = 0;
+            self.calculate_coefficients();
+            return;
+        }
+
+        // Restart the ramp from wherever the current one has got to.
+        self.target_frequency = frequency;
+        self.target_q = q;
+        self.target_gain = gain;
+        self.ramp_steps_left = steps;
+        self.ramp_counter = 0;
+    }
+
+    /// Extra gain in dB for dynamic EQ. Applied immediately, the caller smooths it.
+    pub fn set_gain_offset(&mut self, offset: f32) {
+        if offset != self.gain_offset {
+            self.gain_offset = offset;
+            self.calculate_coefficients();
+        }
+    }
+
+    // Moves 1/steps_left of the remaining distance: linear in log-frequency, log-Q and dB.
+    fn advance_ramp(&mut self) {
+        let steps = self.ramp_steps_left as f32;
+        let log_f = self.frequency.ln() + (self.target_frequency.ln() - self.frequency.ln()) / steps;
+        let log_q = self.q.ln() + (self.target_q.ln() - self.q.ln()) / steps;
+        self.frequency = log_f.exp();
+        self.q = log_q.exp();
+        self.gain += (self.target_gain - self.gain) / steps;
+
+        self.ramp_steps_left -= 1;
+        if self.ramp_steps_left == 0 {
+            self.frequency = self.target_frequency;
+            self.q = self.target_q;
+            self.gain = self.target_gain;
+        }
         self.calculate_coefficients();
     }
 
     fn calculate_coefficients(&mut self) {
-        let w0 = 2.0 * PI as f64 * self.frequency as f64 / self.sample_rate as f64;
-        let cos_w0 = w0.cos();
-        let sin_w0 = w0.sin();
-        let alpha = sin_w0 / (2.0 * self.q as f64);
-        let a = 10.0f64.powf(self.gain as f64 / 40.0); // A = 10^(dB/40) for shelving
-
-        let b0: f64;
-        let b1: f64;
-        let b2: f64;
-        let a0: f64;
-        let a1: f64;
-        let a2: f64;
-
-        match self.filter_type {
-            FilterType::LowPass => {
-                b0 = (1.0 - cos_w0) / 2.0;
-                b1 = 1.0 - cos_w0;
-                b2 = (1.0 - cos_w0) / 2.0;
-                a0 = 1.0 + alpha;
-                a1 = -2.0 * cos_w0;
-                a2 = 1.0 - alpha;
-            },
-            FilterType::HighPass => {
-                b0 = (1.0 + cos_w0) / 2.0;
-                b1 = -(1.0 + cos_w0);
-                b2 = (1.0 + cos_w0) / 2.0;
-                a0 = 1.0 + alpha;
-                a1 = -2.0 * cos_w0;
-                a2 = 1.0 - alpha;
-            },
-            FilterType::BandPass => {
-                b0 = alpha;
-                b1 = 0.0;
-                b2 = -alpha;
-                a0 = 1.0 + alpha;
-                a1 = -2.0 * cos_w0;
-                a2 = 1.0 - alpha;
-            },
-            FilterType::Notch => {
-                b0 = 1.0;
-                b1 = -2.0 * cos_w0;
-                b2 = 1.0;
-                a0 = 1.0 + alpha;
-                a1 = -2.0 * cos_w0;
-                a2 = 1.0 - alpha;
-            },
-            FilterType::LowShelf => {
-                b0 = a * ((a + 1.0) - (a - 1.0) * cos_w0 + 2.0 * a.sqrt() * alpha);
-                b1 = 2.0 * a * ((a - 1.0) - (a + 1.0) * cos_w0);
-                b2 = a * ((a + 1.0) - (a - 1.0) * cos_w0 - 2.0 * a.sqrt() * alpha);
-                a0 = (a + 1.0) + (a - 1.0) * cos_w0 + 2.0 * a.sqrt() * alpha;
-                a1 = -2.0 * ((a - 1.0) + (a + 1.0) * cos_w0);
-                a2 = (a + 1.0) + (a - 1.0) * cos_w0 - 2.0 * a.sqrt() * alpha;
-            },
-            FilterType::HighShelf => {
-                b0 = a * ((a + 1.0) + (a - 1.0) * cos_w0 + 2.0 * a.sqrt() * alpha);
-                b1 = -2.0 * a * ((a - 1.0) + (a + 1.0) * cos_w0);
-                b2 = a * ((a + 1.0) + (a - 1.0) * cos_w0 - 2.0 * a.sqrt() * alpha);
-                a0 = (a + 1.0) - (a - 1.0) * cos_w0 + 2.0 * a.sqrt() * alpha;
-                a1 = 2.0 * ((a - 1.0) - (a + 1.0) * cos_w0);
-                a2 = (a + 1.0) - (a - 1.0) * cos_w0 - 2.0 * a.sqrt() * alpha;
-            },
-            FilterType::Peaking => {
-                let a_peak = 10.0f64.powf(self.gain as f64 / 40.0);
-                b0 = 1.0 + alpha * a_peak;
-                b1 = -2.0 * cos_w0;
-                b2 = 1.0 - alpha * a_peak;
-                a0 = 1.0 + alpha / a_peak;
-                a1 = -2.0 * cos_w0;
-                a2 = 1.0 - alpha / a_peak;
-            },
-            _ => {
-                // Default pass-through
-                b0=1.0; b1=0.0; b2=0.0; a0=1.0; a1=0.0; a2=0.0;
-            }
-        }
+        let [b0, b1, b2, a1, a2] =
+            coefficients(self.kind, self.frequency, self.q, self.gain + self.gain_offset, self.sample_rate);
+        self.b0 = b0;
+        self.b1 = b1;
+        self.b2 = b2;
+        self.a1 = a1;
+        self.a2 = a2;
+    }
 
-        // Normalize
-        self.b0 = b0 / a0;
-        self.b1 = b1 / a0;
-        self.b2 = b2 / a0;
-        self.a1 = a1 / a0;
-        self.a2 = a2 / a0;
+    /// Complex response at `frequency` for the target parameters, i.e. where any
+    /// running parameter ramp will settle. Excludes the dynamic gain offset.
+    pub fn response(&self, frequency: f32) -> Complex<f64> {
+        let [b0, b1, b2, a1, a2] = coefficients(
+            self.kind,
+            self.target_frequency,
+            self.target_q,
+            self.target_gain,
+            self.sample_rate,
+        );
+        let w = 2.0 * PI as f64 * frequency as f64 / self.sample_rate as f64;
+        let z1 = Complex::from_polar(1.0, -w);
+        let z2 = z1 * z1;
+        (z1 * b1 + z2 * b2 + b0) / (z1 * a1 + z2 * a2 + 1.0)
     }
 
     pub fn process(&mut self, input: f32) -> f32 {
+        if self.ramp_steps_left > 0 {
+            if self.ramp_counter == 0 {
+                self.advance_ramp();
+                self.ramp_counter = SMOOTHING_INTERVAL;
+            }
+            self.ramp_counter -= 1;
+        }
+
         let input = input as f64;
         let output = self.b0 * input + self.b1 * self.x1 + self.b2 * self.x2
                    - self.a1 * self.y1 - self.a2 * self.y2;
@@ -158,3 +196,119 @@ impl BiquadFilter {
         output as f32
     }
 }
+
+// Normalized [b0, b1, b2, a1, a2] (RBJ cookbook formulas).
+fn coefficients(kind: SectionKind, frequency: f32, q: f32, gain: f32, sample_rate: f32) -> [f64; 5] {
+    let w0 = 2.0 * PI as f64 * frequency as f64 / sample_rate as f64;
+    let cos_w0 = w0.cos();
+    let sin_w0 = w0.sin();
+    let alpha = sin_w0 / (2.0 * q as f64);
+    let a = 10.0f64.powf(gain as f64 / 40.0); // A = 10^(dB/40) for shelving
+
+    let b0: f64;
+    let b1: f64;
+    let b2: f64;
+    let a0: f64;
+    let a1: f64;
+    let a2: f64;
+
+    match kind {
+        SectionKind::Biquad(FilterType::LowPass) => {
+            b0 = (1.0 - cos_w0) / 2.0;
+            b1 = 1.0 - cos_w0;
+            b2 = (1.0 - cos_w0) / 2.0;
+            a0 = 1.0 + alpha;
+            a1 = -2.0 * cos_w0;
+            a2 = 1.0 - alpha;
+        },
+        SectionKind::Biquad(FilterType::HighPass) => {
+            b0 = (1.0 + cos_w0) / 2.0;
+            b1 = -(1.0 + cos_w0);
+            b2 = (1.0 + cos_w0) / 2.0;
+            a0 = 1.0 + alpha;
+            a1 = -2.0 * cos_w0;
+            a2 = 1.0 - alpha;
+        },
+        SectionKind::Biquad(FilterType::BandPass) => {
+            b0 = alpha;
+            b1 = 0.0;
+            b2 = -alpha;
+            a0 = 1.0 + alpha;
+            a1 = -2.0 * cos_w0;
+            a2 = 1.0 - alpha;
+        },
+        SectionKind::Biquad(FilterType::Notch) => {
+            b0 = 1.0;
+            b1 = -2.0 * cos_w0;
+            b2 = 1.0;
+            a0 = 1.0 + alpha;
+            a1 = -2.0 * cos_w0;
+            a2 = 1.0 - alpha;
+        },
+        SectionKind::Biquad(FilterType::LowShelf) => {
+            b0 = a * ((a + 1.0) - (a - 1.0) * cos_w0 + 2.0 * a.sqrt() * alpha);
+            b1 = 2.0 * a * ((a - 1.0) - (a + 1.0) * cos_w0);
+            b2 = a * ((a + 1.0) - (a - 1.0) * cos_w0 - 2.0 * a.sqrt() * alpha);
+            a0 = (a + 1.0) + (a - 1.0) * cos_w0 + 2.0 * a.sqrt() * alpha;
+            a1 = -2.0 * ((a - 1.0) + (a + 1.0) * cos_w0);
+            a2 = (a + 1.0) + (a - 1.0) * cos_w0 - 2.0 * a.sqrt() * alpha;
+        },
+        SectionKind::Biquad(FilterType::HighShelf) => {
+            b0 = a * ((a + 1.0) + (a - 1.0) * cos_w0 + 2.0 * a.sqrt() * alpha);
+            b1 = -2.0 * a * ((a - 1.0) + (a + 1.0) * cos_w0);
+            b2 = a * ((a + 1.0) + (a - 1.0) * cos_w0 - 2.0 * a.sqrt() * alpha);
+            a0 = (a + 1.0) - (a - 1.0) * cos_w0 + 2.0 * a.sqrt() * alpha;
+            a1 = 2.0 * ((a - 1.0) - (a + 1.0) * cos_w0);
+            a2 = (a + 1.0) - (a - 1.0) * cos_w0 - 2.0 * a.sqrt() * alpha;
+        },
+        SectionKind::LowPass1 => {
+            // First-order (6 dB/oct) section, bilinear transform; Q is unused
+            let k = (w0 / 2.0).tan();
+            b0 = k;
+            b1 = k;
+            b2 = 0.0;
+            a0 = k + 1.0;
+            a1 = k - 1.0;
+            a2 = 0.0;
+        },
+        SectionKind::HighPass1 => {
+            let k = (w0 / 2.0).tan();
+            b0 = 1.0;
+            b1 = -1.0;
+            b2 = 0.0;
+            a0 = k + 1.0;
+            a1 = k - 1.0;
+            a2 = 0.0;
+        },
+        SectionKind::Biquad(FilterType::AllPass) => {
+            b0 = 1.0 - alpha;
+            b1 = -2.0 * cos_w0;
+            b2 = 1.0 + alpha;
+            a0 = 1.0 + alpha;
+            a1 = -2.0 * cos_w0;
+            a2 = 1.0 - alpha;
+        },
+        SectionKind::Biquad(FilterType::TiltShelf) => {
+            // High shelf of the full gain, pulled down by half of it:
+            // -gain/2 below the pivot, +gain/2 above it.
+            let tilt = 1.0 / a;
+            b0 = tilt * a * ((a + 1.0) + (a - 1.0) * cos_w0 + 2.0 * a.sqrt() * alpha);
+            b1 = tilt * -2.0 * a * ((a - 1.0) + (a + 1.0) * cos_w0);
+            b2 = tilt * a * ((a + 1.0) + (a - 1.0) * cos_w0 - 2.0 * a.sqrt() * alpha);
+            a0 = (a + 1.0) - (a - 1.0) * cos_w0 + 2.0 * a.sqrt() * alpha;
+            a1 = 2.0 * ((a - 1.0) - (a + 1.0) * cos_w0);
+            a2 = (a + 1.0) - (a - 1.0) * cos_w0 - 2.0 * a.sqrt() * alpha;
+        },
+        SectionKind::Biquad(FilterType::Peaking) => {
+            let a_peak = 10.0f64.powf(gain as f64 / 40.0);
+            b0 = 1.0 + alpha * a_peak;
+            b1 = -2.0 * cos_w0;
+            b2 = 1.0 - alpha * a_peak;
+            a0 = 1.0 + alpha / a_peak;
+            a1 = -2.0 * cos_w0;
+            a2 = 1.0 - alpha / a_peak;
+        },
+    }
+
+    [b0 / a0, b1 / a0, b2 / a0, a1 / a0, a2 / a0]
+}
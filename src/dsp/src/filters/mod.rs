@@ -1,3 +1,6 @@
 pub mod iir;
 pub mod fir;
-
+pub mod band;
+pub mod design;
+pub mod dynamic;
+pub mod refresh;
@@ -0,0 +1,203 @@
+use crate::config::{EQ_CONFIG, LINEAR_PHASE_CONFIG};
+use super::band::EqBand;
+use super::design::{FirDesigner, FirWindow};
+
+// Work left in a running refresh, in the order it is done
+#[derive(Clone, Copy)]
+enum Stage {
+    Magnitudes(usize), // next bin to sample
+    Headroom,
+    Design,
+}
+
+/// Recomputes what is derived from the band responses (the linear-phase FIR taps and the
+/// auto-headroom gain) a slice at a time, so a band change adds a bounded amount of work
+/// to each audio block instead of a whole redesign to one of them. Buffers and FFT plans
+/// are allocated once, up front.
+pub struct BandRefresh {
+    sample_rate: f32,
+    bin_size: f32,
+    magnitudes: [Vec<f32>; 2],
+    taps: [Vec<f32>; 2],
+    designer: FirDesigner,
+    stage: Option<Stage>,
+
+    // What the running (or last finished) refresh computes
+    design_taps: bool,
+    scan_headroom: bool,
+    // Linked bands design the left channel only and copy it to the right
+    linked: bool,
+    headroom_gain: f32,
+}
+
+impl BandRefresh {
+    pub fn new(sample_rate: f32) -> Self {
+        let fft_size = LINEAR_PHASE_CONFIG.design_fft_size;
+        let bins = fft_size / 2 + 1;
+        let taps = LINEAR_PHASE_CONFIG.num_taps;
+        Self {
+            sample_rate,
+            bin_size: sample_rate / fft_size as f32,
+            magnitudes: [vec![0.0; bins], vec![0.0; bins]],
+            taps: [vec![0.0; taps], vec![0.0; taps]],
+            designer: FirDesigner::new(fft_size),
+            stage: None,
+            design_taps: false,
+            scan_headroom: false,
+            linked: false,
+            headroom_gain: 1.0,
+        }
+    }
+
+    /// Starts a refresh from scratch, abandoning any that is running. `design_taps` redesigns
+    /// the linear-phase FIR, `scan_headroom` finds the auto-headroom gain.
+    pub fn start(&mut self, linked: bool, design_taps: bool, scan_headroom: bool) {
+        self.linked = linked;
+        self.design_taps = design_taps;
+        self.scan_headroom = scan_headroom;
+        self.stage = if design_taps {
+            Some(Stage::Magnitudes(0))
+        } else if scan_headroom {
+            Some(Stage::Headroom)
+        } else {
+            None
+        };
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.stage.is_some()
+    }
+
+    /// Does one block's share of the running refresh: `bins_per_block` response bins per
+    /// channel, the headroom scan, or the design itself. Returns true on the call that finishes it.
+    pub fn step(&mut self, bands_l: &[EqBand], bands_r: &[EqBand]) -> bool {
+        let Some(stage) = self.stage else {
+            return false;
+        };
+        let channels = if self.linked { 1 } else { 2 };
+
+        match stage {
+            Stage::Magnitudes(start) => {
+                let bins = self.magnitudes[0].len();
+                let end = (start + LINEAR_PHASE_CONFIG.bins_per_block).min(bins);
+                for (magnitudes, bands) in self.magnitudes.iter_mut().zip([bands_l, bands_r]).take(channels) {
+                    for (k, magnitude) in magnitudes.iter_mut().enumerate().take(end).skip(start) {
+                        *magnitude = combined_magnitude(bands, k as f32 * self.bin_size) as f32;
+                    }
+                }
+                self.stage = Some(if end < bins {
+                    Stage::Magnitudes(end)
+                } else if self.scan_headroom {
+                    Stage::Headroom
+                } else {
+                    Stage::Design
+                });
+                false
+            }
+            Stage::Headroom => {
+                self.headroom_gain = self.scan_headroom_gain(bands_l, bands_r);
+                self.stage = self.design_taps.then_some(Stage::Design);
+                self.stage.is_none()
+            }
+            Stage::Design => {
+                for channel in 0..channels {
+                    self.designer.design(&self.magnitudes[channel], &mut self.taps[channel], 0.0, FirWindow::Blackman);
+                }
+                if self.linked {
+                    let [taps_l, taps_r] = &mut self.taps;
+                    taps_r.copy_from_slice(taps_l);
+                }
+                self.stage = None;
+                true
+            }
+        }
+    }
+
+    /// Taps of the last finished refresh, 0 for left (mid) and 1 for right (side).
+    /// `None` if it did not design any.
+    pub fn taps(&self, channel: usize) -> Option<&[f32]> {
+        self.design_taps.then(|| self.taps[channel.min(1)].as_slice())
+    }
+
+    /// Auto-headroom gain found by the last finished refresh, `None` if it did not scan for one.
+    pub fn headroom_gain(&self) -> Option<f32> {
+        self.scan_headroom.then_some(self.headroom_gain)
+    }
+
+    fn scan_headroom_gain(&self, bands_l: &[EqBand], bands_r: &[EqBand]) -> f32 {
+        // Peak of the band magnitude response on a log grid over the audible range.
+        // The louder of the two band sets also bounds the mid/side re-matrixed output.
+        let points = EQ_CONFIG.headroom_grid_points;
+        let low = 20.0f32;
+        let high = 20000.0f32.min(self.sample_rate * 0.5 * 0.999);
+        let mut peak = 1.0f64;
+        for n in 0..points {
+            let freq = low * (high / low).powf(n as f32 / (points - 1) as f32);
+            for bands in [bands_l, bands_r] {
+                peak = peak.max(combined_magnitude(bands, freq));
+            }
+        }
+        (1.0 / peak) as f32
+    }
+}
+
+fn combined_magnitude(bands: &[EqBand], freq: f32) -> f64 {
+    bands.iter().map(|band| band.response(freq).norm()).product()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filters::iir::FilterType;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    // Gain in dB of `taps` at `freq`, from their DFT at that one frequency.
+    fn gain_db(taps: &[f32], freq: f32) -> f32 {
+        let omega = 2.0 * std::f32::consts::PI * freq / SAMPLE_RATE;
+        let (re, im) = taps.iter().enumerate().fold((0.0, 0.0), |(re, im), (n, &tap)| {
+            (re + tap * (omega * n as f32).cos(), im - tap * (omega * n as f32).sin())
+        });
+        20.0 * (re * re + im * im).sqrt().log10()
+    }
+
+    #[test]
+    fn design_is_spread_over_blocks_and_matches_the_bands() {
+        let mut band = EqBand::new(SAMPLE_RATE, 0.0);
+        band.set_params(FilterType::Peaking, 1000.0, 1.0, 6.0);
+        let bands = [band];
+
+        let mut refresh = BandRefresh::new(SAMPLE_RATE);
+        refresh.start(true, true, false);
+        let mut blocks = 1;
+        while !refresh.step(&bands, &bands) {
+            blocks += 1;
+        }
+        assert!(!refresh.is_running());
+        let bins = LINEAR_PHASE_CONFIG.design_fft_size / 2 + 1;
+        assert_eq!(blocks, bins.div_ceil(LINEAR_PHASE_CONFIG.bins_per_block) + 1);
+
+        for (freq, expected) in [(100.0, 0.0), (1000.0, 6.0), (10000.0, 0.0)] {
+            for channel in 0..2 {
+                let gain = gain_db(refresh.taps(channel).unwrap(), freq);
+                assert!((gain - expected).abs() < 0.1, "{freq} Hz: {gain} dB");
+            }
+        }
+        assert_eq!(refresh.headroom_gain(), None);
+    }
+
+    #[test]
+    fn headroom_cancels_the_peak_boost() {
+        let mut boost = EqBand::new(SAMPLE_RATE, 0.0);
+        boost.set_params(FilterType::Peaking, 1000.0, 1.0, 6.0);
+        let mut cut = EqBand::new(SAMPLE_RATE, 0.0);
+        cut.set_params(FilterType::Peaking, 100.0, 1.0, -6.0);
+
+        let mut refresh = BandRefresh::new(SAMPLE_RATE);
+        refresh.start(false, false, true);
+        assert!(refresh.step(&[boost], &[cut]));
+        let gain_db = 20.0 * refresh.headroom_gain().unwrap().log10();
+        assert!((gain_db + 6.0).abs() < 0.05, "{gain_db} dB");
+        assert!(refresh.taps(0).is_none());
+    }
+}
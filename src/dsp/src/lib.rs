@@ -1,24 +1,70 @@
 use wasm_bindgen::prelude::*;
+use rustfft::num_complex::Complex;
 
 mod filters;
 mod dynamics;
 mod analysis;
 mod sbr;
 mod config;
+mod apo;
 
-use filters::iir::{BiquadFilter, FilterType};
+use filters::iir::FilterType;
+use filters::band::{EqBand, FilterSlope, SlopeAlignment};
+use filters::fir::FirFilter;
+use filters::refresh::BandRefresh;
 use dynamics::compressor::DynamicsProcessor;
+use dynamics::multiband::MultibandCompressor;
+use dynamics::gate::Gate;
 use sbr::SBRProcessor;
+use analysis::cutoff::CutoffDetector;
 use analysis::fft::FftAnalyzer;
+use config::{ConfigError, LimiterConfig, SbrConfig, EQ_CONFIG, LINEAR_PHASE_CONFIG};
+use apo::ApoParseError;
+
+// How the two band sets map onto the signal. In MidSide mode the left set processes
+// the mid signal and the right set the side signal.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChannelMode {
+    Linked = 0,
+    LeftRight = 1,
+    MidSide = 2,
+}
 
 #[wasm_bindgen]
 pub struct JuraganAudioDSP {
-    filters_l: Vec<BiquadFilter>,
-    filters_r: Vec<BiquadFilter>,
+    filters_l: Vec<EqBand>,
+    filters_r: Vec<EqBand>,
+    // Removed bands still fading out, processed after the live ones until they are flat
+    retiring_l: Vec<EqBand>,
+    retiring_r: Vec<EqBand>,
+    channel_mode: ChannelMode,
+    max_bands: usize,
+    smoothing_ms: f32,
+
+    // Linear-phase mode: FIR designed from the band magnitudes replaces the IIR cascade
+    // once `refresh` has finished its first design
+    linear_phase: bool,
+    fir_l: Option<FirFilter>,
+    fir_r: Option<FirFilter>,
+    refresh: BandRefresh,
+
+    // Auto headroom: pre-gain that cancels the peak boost of the band response
+    auto_headroom: bool,
+    headroom_target: f32,
+    headroom_gain: f32,
+    headroom_coeff: f32,
+
+    bands_dirty: bool,
+
+    gate: Gate,
+    multiband: MultibandCompressor,
     dynamics: DynamicsProcessor,
     sbr: SBRProcessor,
     fft_analyzer: FftAnalyzer,
+    cutoff_detector: CutoffDetector,
     gain: f32,
+    preamp: f32,
     
     // Internal Analysis for SBR Trigger
     analysis_buffer: Vec<f32>,
@@ -31,20 +77,39 @@ pub struct JuraganAudioDSP {
 impl JuraganAudioDSP {
     #[wasm_bindgen(constructor)]
     pub fn new(sample_rate: f32) -> Self {
-        let mut filters_l = Vec::with_capacity(11);
-        let mut filters_r = Vec::with_capacity(11);
-        for _ in 0..11 {
-            filters_l.push(BiquadFilter::new(sample_rate));
-            filters_r.push(BiquadFilter::new(sample_rate));
+        let max_bands = EQ_CONFIG.max_bands;
+        let mut filters_l = Vec::with_capacity(max_bands);
+        let mut filters_r = Vec::with_capacity(max_bands);
+        for _ in 0..EQ_CONFIG.default_bands {
+            filters_l.push(EqBand::new(sample_rate, EQ_CONFIG.smoothing_ms));
+            filters_r.push(EqBand::new(sample_rate, EQ_CONFIG.smoothing_ms));
         }
         
         Self {
             filters_l,
             filters_r,
+            retiring_l: Vec::new(),
+            retiring_r: Vec::new(),
+            channel_mode: ChannelMode::Linked,
+            max_bands,
+            smoothing_ms: EQ_CONFIG.smoothing_ms,
+            linear_phase: false,
+            fir_l: None,
+            fir_r: None,
+            refresh: BandRefresh::new(sample_rate),
+            auto_headroom: false,
+            headroom_target: 1.0,
+            headroom_gain: 1.0,
+            headroom_coeff: (-1.0 / (EQ_CONFIG.headroom_ramp_ms / 1000.0 * sample_rate)).exp(),
+            bands_dirty: false,
+            gate: Gate::new(sample_rate),
+            multiband: MultibandCompressor::new(sample_rate),
             dynamics: DynamicsProcessor::new(sample_rate),
             sbr: SBRProcessor::new(sample_rate),
             fft_analyzer: FftAnalyzer::new(4096),
+            cutoff_detector: CutoffDetector::new(4096, sample_rate),
             gain: 1.0,
+            preamp: 1.0,
             
             analysis_buffer: vec![0.0; 4096],
             analysis_pos: 0,
@@ -57,10 +122,21 @@ impl JuraganAudioDSP {
         self.gain = val;
     }
 
+    /// Preamp in dB, applied in the gain stage on top of `set_gain`.
+    pub fn set_preamp_db(&mut self, db: f32) {
+        self.preamp = 10.0f32.powf(db / 20.0);
+    }
+
     pub fn set_limiter_options(&mut self, enabled: bool, attack: f32) {
         self.dynamics.set_limiter_options(enabled, attack);
     }
 
+    /// Release in milliseconds for the limiter stages. `auto_release` adapts between this
+    /// fast release and a slower one while the signal keeps hitting the threshold.
+    pub fn set_limiter_release(&mut self, release_ms: f32, auto_release: bool) {
+        self.dynamics.set_limiter_release(release_ms, auto_release);
+    }
+
     pub fn set_limiter_params(
         &mut self,
         threshold: f32,
@@ -72,24 +148,537 @@ impl JuraganAudioDSP {
         self.dynamics
             .set_limiter_params(threshold, knee, detector_mode, lookahead_ms, rms_time_ms);
     }
+
+    /// Applies a full `LimiterConfig` (threshold, knee, RMS time, lookahead, release and
+    /// the auto-release shape) in one call. Fails without changing anything if invalid.
+    pub fn set_limiter_config(&mut self, config: &LimiterConfig) -> Result<(), ConfigError> {
+        self.dynamics.set_limiter_config(config)
+    }
+
+    /// Current limiter tuning, including changes made through the individual setters.
+    pub fn get_limiter_config(&self) -> LimiterConfig {
+        self.dynamics.get_limiter_config()
+    }
     
+    /// Deepest gain reduction since the last call, in dB. The worklet reads it once per
+    /// analyzer frame together with `get_true_peak_db`.
     pub fn get_reduction_db(&mut self) -> f32 {
         self.dynamics.get_reduction_db()
     }
 
-    pub fn set_filter(&mut self, index: usize, type_id: u8, freq: f32, q: f32, gain: f32) {
+    /// Sidechain filter for the leveller detector: `HighPass` at `frequency` keeps the kick
+    /// and sub-bass from ducking everything else, `KWeighting` follows perceived loudness.
+    pub fn set_sidechain_filter(&mut self, mode: dynamics::sidechain::SidechainMode, frequency: f32) {
+        self.dynamics.set_sidechain_filter(mode, frequency);
+    }
+
+    /// Last dynamics stage: the leveller with soft clipper (default) or the brick-wall
+    /// lookahead limiter. The limiter's lookahead adds to `latency_samples`.
+    pub fn set_final_stage(&mut self, stage: dynamics::compressor::FinalStage) {
+        self.dynamics.set_final_stage(stage);
+    }
+
+    pub fn get_final_stage(&self) -> dynamics::compressor::FinalStage {
+        self.dynamics.get_final_stage()
+    }
+
+    /// Safety clipper of the leveller stage: curve, drive in dB (the "warmth" control),
+    /// linear ceiling and 1x/2x/4x oversampling. Bypassed with the limiter.
+    pub fn set_clipper_params(
+        &mut self,
+        curve: dynamics::clipper::ClipCurve,
+        drive_db: f32,
+        ceiling: f32,
+        oversampling: usize,
+    ) {
+        self.dynamics.set_clipper_params(curve, drive_db, ceiling, oversampling);
+    }
+
+    /// Limiter detection on the 4x oversampled signal (ITU-R BS.1770 true peak), so
+    /// inter-sample peaks stay under the threshold. Applies to the Peak detector mode.
+    /// Only detection is oversampled: the lookahead gain is smooth enough that applying
+    /// it at the base rate holds the true-peak ceiling without another resampler.
+    pub fn set_true_peak_limiting(&mut self, enabled: bool) {
+        self.dynamics.set_true_peak_limiting(enabled);
+    }
+
+    /// Highest true-peak level of the output since the last call, in dBTP.
+    /// Companion to `get_reduction_db`, read at the same rate; metered even with the
+    /// compressor and limiter off.
+    pub fn get_true_peak_db(&mut self) -> f32 {
+        self.dynamics.get_true_peak_db()
+    }
+
+    /// Stereo link from 0 (independent) to 1 (fully linked) for the compressor and limiter
+    /// detectors, linking to the louder or the average channel level. With `mid_side` the
+    /// detectors and gain work on mid and side rather than left and right.
+    pub fn set_stereo_link(&mut self, amount: f32, mode: dynamics::compressor::StereoLinkMode, mid_side: bool) {
+        self.dynamics.set_stereo_link(amount, mode, mid_side);
+    }
+
+    /// Per-channel limiter reduction in dB: 0 is left (mid), 1 is right (side).
+    pub fn get_channel_reduction_db(&mut self, channel: usize) -> f32 {
+        self.dynamics.get_channel_reduction_db(channel)
+    }
+
+    /// Single-band downward compressor, run ahead of the limiter. Off by default.
+    pub fn set_compressor_enabled(&mut self, enabled: bool) {
+        self.dynamics.set_compressor_enabled(enabled);
+    }
+
+    pub fn is_compressor_enabled(&self) -> bool {
+        self.dynamics.is_compressor_enabled()
+    }
+
+    /// Threshold in dBFS, knee and makeup in dB, attack/release in milliseconds.
+    /// With `auto_makeup` the makeup gain follows the threshold and ratio instead.
+    #[allow(clippy::too_many_arguments)]
+    pub fn set_compressor_params(
+        &mut self,
+        threshold_db: f32,
+        ratio: f32,
+        knee_db: f32,
+        attack_ms: f32,
+        release_ms: f32,
+        makeup_db: f32,
+        auto_makeup: bool,
+    ) {
+        self.dynamics
+            .set_compressor_params(threshold_db, ratio, knee_db, attack_ms, release_ms, makeup_db, auto_makeup);
+    }
+
+    pub fn get_compressor_reduction_db(&mut self) -> f32 {
+        self.dynamics.get_compressor_reduction_db()
+    }
+
+    /// Gate/expander on the input, ahead of the EQ, so boosted bands do not lift hiss.
+    /// Off by default.
+    pub fn set_gate_enabled(&mut self, enabled: bool) {
+        self.gate.set_enabled(enabled);
+    }
+
+    pub fn is_gate_enabled(&self) -> bool {
+        self.gate.is_enabled()
+    }
+
+    /// Threshold in dBFS, range (maximum attenuation) and hysteresis in dB, times in
+    /// milliseconds. A high ratio gates, a low one expands gently.
+    #[allow(clippy::too_many_arguments)]
+    pub fn set_gate_params(
+        &mut self,
+        threshold_db: f32,
+        range_db: f32,
+        ratio: f32,
+        attack_ms: f32,
+        hold_ms: f32,
+        release_ms: f32,
+        hysteresis_db: f32,
+    ) {
+        self.gate
+            .set_params(threshold_db, range_db, ratio, attack_ms, hold_ms, release_ms, hysteresis_db);
+    }
+
+    /// Band-pass on the gate detector at `freq`/`q`; `enabled = false` detects full band.
+    pub fn set_gate_sidechain(&mut self, enabled: bool, freq: f32, q: f32) {
+        self.gate.set_sidechain(enabled, freq, q);
+    }
+
+    pub fn is_gate_open(&self) -> bool {
+        self.gate.is_open()
+    }
+
+    /// Deepest gate attenuation since the last call, in dB.
+    pub fn get_gate_reduction_db(&mut self) -> f32 {
+        self.gate.get_reduction_db()
+    }
+
+    /// Multiband compressor, run after SBR and before the limiter. Off by default.
+    pub fn set_multiband_enabled(&mut self, enabled: bool) {
+        self.multiband.set_enabled(enabled);
+    }
+
+    pub fn is_multiband_enabled(&self) -> bool {
+        self.multiband.is_enabled()
+    }
+
+    /// 3 to 5 bands. Resets the crossovers to their default spacing.
+    pub fn set_multiband_band_count(&mut self, count: usize) {
+        self.multiband.set_band_count(count);
+    }
+
+    pub fn multiband_band_count(&self) -> usize {
+        self.multiband.band_count()
+    }
+
+    pub fn set_multiband_crossover(&mut self, index: usize, freq: f32) {
+        self.multiband.set_crossover(index, freq);
+    }
+
+    pub fn get_multiband_crossover(&self, index: usize) -> f32 {
+        self.multiband.crossover(index)
+    }
+
+    /// Threshold in dBFS, knee width and makeup in dB, attack/release in milliseconds.
+    #[allow(clippy::too_many_arguments)]
+    pub fn set_multiband_band(
+        &mut self,
+        band: usize,
+        threshold_db: f32,
+        ratio: f32,
+        knee_db: f32,
+        attack_ms: f32,
+        release_ms: f32,
+        makeup_db: f32,
+    ) {
+        self.multiband
+            .set_band_params(band, threshold_db, ratio, knee_db, attack_ms, release_ms, makeup_db);
+    }
+
+    pub fn set_multiband_band_solo(&mut self, band: usize, solo: bool) {
+        self.multiband.set_band_solo(band, solo);
+    }
+
+    pub fn set_multiband_band_bypass(&mut self, band: usize, bypass: bool) {
+        self.multiband.set_band_bypass(band, bypass);
+    }
+
+    /// Deepest gain reduction of a multiband band since the last call, in dB.
+    pub fn get_multiband_reduction_db(&mut self, band: usize) -> f32 {
+        self.multiband.get_band_reduction_db(band)
+    }
+
+    pub fn set_filter(&mut self, index: usize, filter_type: FilterType, freq: f32, q: f32, gain: f32) {
         if index < self.filters_l.len() {
-             // 0: LowShelf, 1: Peaking, 2: HighShelf
-             let filter_type = match type_id {
-                0 => FilterType::LowShelf,
-                2 => FilterType::HighShelf,
-                _ => FilterType::Peaking,
-            };
             self.filters_l[index].set_params(filter_type, freq, q, gain);
             self.filters_r[index].set_params(filter_type, freq, q, gain);
+            self.bands_changed();
+        }
+    }
+
+    /// Roll-off for LowPass/HighPass bands. Other band types ignore it.
+    pub fn set_filter_slope(&mut self, index: usize, slope: FilterSlope, alignment: SlopeAlignment) {
+        if index < self.filters_l.len() {
+            self.filters_l[index].set_slope(slope, alignment);
+            self.filters_r[index].set_slope(slope, alignment);
+            self.bands_changed();
+        }
+    }
+
+    /// Makes a band dynamic: its gain is cut while the band's region of the input is above
+    /// `threshold_db` (dBFS), by `ratio` like a compressor. `enabled = false` restores the
+    /// static band. Dynamic bands run in the IIR cascade only, not in linear-phase mode.
+    #[allow(clippy::too_many_arguments)]
+    pub fn set_filter_dynamics(
+        &mut self,
+        index: usize,
+        enabled: bool,
+        threshold_db: f32,
+        ratio: f32,
+        attack_ms: f32,
+        release_ms: f32,
+        detector_mode: dynamics::compressor::DetectorMode,
+    ) {
+        if index >= self.filters_l.len() {
+            return;
+        }
+        for band in [&mut self.filters_l[index], &mut self.filters_r[index]] {
+            if enabled {
+                band.set_dynamics(threshold_db, ratio, attack_ms, release_ms, detector_mode);
+            } else {
+                band.clear_dynamics();
+            }
+        }
+    }
+
+    /// Current dynamic gain change of a band in dB (the larger cut of the two channels).
+    pub fn get_band_dynamic_gain_db(&self, index: usize) -> f32 {
+        match (self.filters_l.get(index), self.filters_r.get(index)) {
+            (Some(band_l), Some(band_r)) => band_l.dynamic_gain_db().min(band_r.dynamic_gain_db()),
+            _ => 0.0,
+        }
+    }
+
+    /// Sets a band on one channel only: 0 is left (mid in MidSide mode), 1 is right (side).
+    /// In Linked mode both channels are written, like `set_filter`.
+    pub fn set_channel_filter(
+        &mut self,
+        channel: usize,
+        index: usize,
+        filter_type: FilterType,
+        freq: f32,
+        q: f32,
+        gain: f32,
+    ) {
+        if self.channel_mode == ChannelMode::Linked {
+            self.set_filter(index, filter_type, freq, q, gain);
+            return;
+        }
+        if let Some(band) = self.channel_bands_mut(channel).and_then(|bands| bands.get_mut(index)) {
+            band.set_params(filter_type, freq, q, gain);
+            self.bands_changed();
+        }
+    }
+
+    /// Per-channel counterpart of `set_filter_slope`, same channel numbering as `set_channel_filter`.
+    pub fn set_channel_filter_slope(
+        &mut self,
+        channel: usize,
+        index: usize,
+        slope: FilterSlope,
+        alignment: SlopeAlignment,
+    ) {
+        if self.channel_mode == ChannelMode::Linked {
+            self.set_filter_slope(index, slope, alignment);
+            return;
+        }
+        if let Some(band) = self.channel_bands_mut(channel).and_then(|bands| bands.get_mut(index)) {
+            band.set_slope(slope, alignment);
+            self.bands_changed();
         }
     }
 
+    /// Per-channel counterpart of `set_filter_dynamics`, same channel numbering as `set_channel_filter`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn set_channel_filter_dynamics(
+        &mut self,
+        channel: usize,
+        index: usize,
+        enabled: bool,
+        threshold_db: f32,
+        ratio: f32,
+        attack_ms: f32,
+        release_ms: f32,
+        detector_mode: dynamics::compressor::DetectorMode,
+    ) {
+        if self.channel_mode == ChannelMode::Linked {
+            self.set_filter_dynamics(index, enabled, threshold_db, ratio, attack_ms, release_ms, detector_mode);
+            return;
+        }
+        if let Some(band) = self.channel_bands_mut(channel).and_then(|bands| bands.get_mut(index)) {
+            if enabled {
+                band.set_dynamics(threshold_db, ratio, attack_ms, release_ms, detector_mode);
+            } else {
+                band.clear_dynamics();
+            }
+        }
+    }
+
+    /// Switching to Linked copies the left (mid) settings to the right (side) bands.
+    pub fn set_channel_mode(&mut self, mode: ChannelMode) {
+        if mode == self.channel_mode {
+            return;
+        }
+        self.channel_mode = mode;
+        if mode == ChannelMode::Linked {
+            for (band_r, band_l) in self.filters_r.iter_mut().zip(&self.filters_l) {
+                band_r.copy_settings(band_l);
+            }
+        }
+        self.bands_changed();
+    }
+
+    pub fn get_channel_mode(&self) -> ChannelMode {
+        self.channel_mode
+    }
+
+    /// Ramp time for band frequency/Q/gain changes, in milliseconds. 0 disables smoothing.
+    pub fn set_filter_smoothing(&mut self, ramp_ms: f32) {
+        self.smoothing_ms = ramp_ms.max(0.0);
+        for filter in self.filters_l.iter_mut().chain(self.filters_r.iter_mut()) {
+            filter.set_ramp_time(self.smoothing_ms);
+        }
+    }
+
+    pub fn band_count(&self) -> usize {
+        self.filters_l.len()
+    }
+
+    pub fn max_bands(&self) -> usize {
+        self.max_bands
+    }
+
+    /// Sets the band limit. Bands beyond the new limit are faded out and dropped from the end.
+    pub fn set_max_bands(&mut self, max: usize) {
+        self.max_bands = max.max(1);
+        if self.filters_l.len() > self.max_bands {
+            self.set_band_count(self.max_bands);
+        }
+    }
+
+    /// Grows or shrinks the band list at the end. New bands start flat,
+    /// existing bands keep their settings and filter state. Removed bands fade out.
+    pub fn set_band_count(&mut self, count: usize) {
+        let count = count.min(self.max_bands);
+        if self.filters_l.len() > count {
+            let removed_l: Vec<EqBand> = self.filters_l.drain(count..).collect();
+            let removed_r: Vec<EqBand> = self.filters_r.drain(count..).collect();
+            self.retire_bands(removed_l, removed_r);
+        }
+        while self.filters_l.len() < count {
+            self.filters_l.push(EqBand::new(self.sample_rate, self.smoothing_ms));
+            self.filters_r.push(EqBand::new(self.sample_rate, self.smoothing_ms));
+        }
+        self.bands_changed();
+    }
+
+    /// Inserts a flat band at `index` (appends if past the end).
+    /// Returns false if the band limit is reached.
+    pub fn insert_band(&mut self, index: usize) -> bool {
+        if self.filters_l.len() >= self.max_bands {
+            return false;
+        }
+        let index = index.min(self.filters_l.len());
+        self.filters_l.insert(index, EqBand::new(self.sample_rate, self.smoothing_ms));
+        self.filters_r.insert(index, EqBand::new(self.sample_rate, self.smoothing_ms));
+        self.bands_changed();
+        true
+    }
+
+    pub fn remove_band(&mut self, index: usize) -> bool {
+        if index >= self.filters_l.len() {
+            return false;
+        }
+        let band_l = self.filters_l.remove(index);
+        let band_r = self.filters_r.remove(index);
+        self.retire_bands(vec![band_l], vec![band_r]);
+        self.bands_changed();
+        true
+    }
+
+    /// Moves a band to a new position. The band carries its filter state along,
+    /// so reordering does not reset it.
+    pub fn move_band(&mut self, from: usize, to: usize) -> bool {
+        let len = self.filters_l.len();
+        if from >= len || to >= len {
+            return false;
+        }
+        let band_l = self.filters_l.remove(from);
+        let band_r = self.filters_r.remove(from);
+        self.filters_l.insert(to, band_l);
+        self.filters_r.insert(to, band_r);
+        true
+    }
+
+    /// Automatically lowers the input ahead of the bands by the peak boost of their combined
+    /// response, so boosted presets do not drive the limiter. Changes are ramped.
+    pub fn set_auto_headroom(&mut self, enabled: bool) {
+        self.auto_headroom = enabled;
+        if enabled {
+            self.bands_changed();
+        } else {
+            self.headroom_target = 1.0;
+        }
+    }
+
+    /// Current auto-headroom pre-gain in dB (0 or negative).
+    pub fn get_headroom_db(&self) -> f32 {
+        20.0 * self.headroom_gain.log10()
+    }
+
+    /// Response of the whole EQ (all bands, auto headroom, gain and preamp) at each of `frequencies`,
+    /// in the style of Web Audio's `getFrequencyResponse`: linear magnitude and phase in
+    /// radians. Reflects the settled band parameters, and the pure delay of linear-phase mode.
+    /// This is the left (mid) channel, see `get_channel_frequency_response`.
+    pub fn get_frequency_response(&self, frequencies: &[f32], magnitude: &mut [f32], phase: &mut [f32]) {
+        self.get_channel_frequency_response(0, frequencies, magnitude, phase);
+    }
+
+    /// `get_frequency_response` for one channel: 0 is left (mid in MidSide mode), 1 is right (side).
+    /// Leaves the outputs untouched for an invalid channel.
+    pub fn get_channel_frequency_response(
+        &self,
+        channel: usize,
+        frequencies: &[f32],
+        magnitude: &mut [f32],
+        phase: &mut [f32],
+    ) {
+        let Some(bands) = self.channel_bands(channel) else {
+            return;
+        };
+        let scale = (self.headroom_target * self.gain * self.preamp) as f64;
+        for (i, &freq) in frequencies.iter().enumerate() {
+            let response = bands
+                .iter()
+                .fold(Complex::new(scale, 0.0), |acc, band| acc * band.response(freq));
+            let arg = match &self.fir_l {
+                Some(fir) => {
+                    let delay = (LINEAR_PHASE_CONFIG.num_taps - 1) / 2 + fir.block_latency();
+                    -2.0 * std::f64::consts::PI * freq as f64 / self.sample_rate as f64 * delay as f64
+                }
+                None => response.arg(),
+            };
+            write_response(i, response.norm(), arg, magnitude, phase);
+        }
+    }
+
+    /// Response of a single band, same conventions as `get_frequency_response`
+    /// (without gain and preamp). Leaves the outputs untouched for an invalid index.
+    pub fn get_band_frequency_response(
+        &self,
+        index: usize,
+        frequencies: &[f32],
+        magnitude: &mut [f32],
+        phase: &mut [f32],
+    ) {
+        let Some(band) = self.filters_l.get(index) else {
+            return;
+        };
+        for (i, &freq) in frequencies.iter().enumerate() {
+            let response = band.response(freq);
+            write_response(i, response.norm(), response.arg(), magnitude, phase);
+        }
+    }
+
+    /// Loads an Equalizer APO / AutoEQ `ParametricEQ.txt` profile: one band per active
+    /// filter, and the profile's preamp. Nothing changes if parsing fails.
+    /// Returns the number of bands loaded.
+    pub fn load_parametric_eq(&mut self, text: &str) -> Result<usize, ApoParseError> {
+        let profile = apo::parse_parametric_eq(text, self.max_bands)?;
+
+        self.set_band_count(profile.filters.len());
+        for band in self.filters_l.iter_mut().chain(self.filters_r.iter_mut()) {
+            // Profiles describe static bands; dynamics set on a kept band would linger
+            band.clear_dynamics();
+        }
+        for (index, filter) in profile.filters.iter().enumerate() {
+            // APO pass filters are plain 12 dB/oct sections
+            self.set_filter_slope(index, FilterSlope::Db12, SlopeAlignment::Butterworth);
+            self.set_filter(index, filter.filter_type, filter.frequency, filter.q, filter.gain);
+        }
+        self.set_preamp_db(profile.preamp_db);
+
+        Ok(profile.filters.len())
+    }
+
+    /// Switches the EQ between the IIR band cascade and a linear-phase FIR with the
+    /// same magnitude response. The FIR takes over when its first design is done, a few
+    /// blocks later. Linear phase adds latency, see `latency_samples`.
+    pub fn set_linear_phase(&mut self, enabled: bool) {
+        if enabled == self.linear_phase {
+            return;
+        }
+        self.linear_phase = enabled;
+        if enabled {
+            self.bands_changed();
+        } else {
+            self.fir_l = None;
+            self.fir_r = None;
+        }
+    }
+
+    pub fn is_linear_phase(&self) -> bool {
+        self.linear_phase
+    }
+
+    /// Total processing delay of the chain in samples (linear-phase EQ, spectral SBR and limiter lookahead).
+    pub fn latency_samples(&self) -> usize {
+        let eq = match &self.fir_l {
+            Some(fir) => (LINEAR_PHASE_CONFIG.num_taps - 1) / 2 + fir.block_latency(),
+            None => 0,
+        };
+        eq + self.sbr.latency_samples() + self.dynamics.latency_samples()
+    }
+
     pub fn set_sbr_options(&mut self, enabled: bool, gain: f32) {
         self.sbr.set_options(enabled, gain);
         if !enabled {
@@ -98,30 +687,104 @@ impl JuraganAudioDSP {
         }
     }
     
+    /// Exciter (default) is the original zero-latency generator; Spectral replicates
+    /// lowband content above the detected cutoff and adds one STFT frame of latency.
+    pub fn set_sbr_mode(&mut self, mode: sbr::SbrMode) {
+        self.sbr.set_mode(mode);
+    }
+
+    pub fn get_sbr_mode(&self) -> sbr::SbrMode {
+        self.sbr.get_mode()
+    }
+
+    /// Applies a full `SbrConfig` (detection, exciter envelopes and gains, patch band
+    /// edges and tilt, fade times) in one call. An invalid set is rejected as a whole
+    /// with a `ConfigError` naming the offending field, and the current one stays.
+    pub fn set_sbr_config(&mut self, config: &SbrConfig) -> Result<(), ConfigError> {
+        self.sbr.set_config(config)
+    }
+
+    pub fn get_sbr_config(&self) -> SbrConfig {
+        self.sbr.get_config()
+    }
+
+    /// Brick-wall lowpass of the source in Hz, 0 for a full-band source or while unknown.
+    pub fn estimated_cutoff_hz(&self) -> f32 {
+        self.cutoff_detector.cutoff_hz().unwrap_or(0.0)
+    }
+
+    /// Confidence of the latest cutoff measurement, 0 to 1.
+    pub fn get_cutoff_confidence(&self) -> f32 {
+        self.cutoff_detector.confidence()
+    }
+
+    /// MP3 bitrate typical for the detected cutoff (e.g. 128 for 16 kHz), 0 if none.
+    pub fn estimated_bitrate_kbps(&self) -> u32 {
+        self.cutoff_detector.estimated_bitrate_kbps()
+    }
+
     pub fn is_sbr_active(&self) -> bool {
         self.sbr.is_enabled() && self.sbr_active_timer > 0
     }
+
+    /// Level of the generated highband, 0 to 1. Ramps with the SBR config's fade times
+    /// after `is_sbr_active` flips, so the popup can show partial activation.
+    pub fn get_sbr_wet_level(&self) -> f32 {
+        self.sbr.wet_level()
+    }
     
     pub fn process_stereo(&mut self, input_l: &[f32], input_r: &[f32], output_l: &mut [f32], output_r: &mut [f32]) {
         let len = input_l.len().min(input_r.len()).min(output_l.len()).min(output_r.len());
+
+        self.refresh_bands();
         
-        // 1. EQ & Gain (Apply to output buffer first by copying input)
+        let mid_side = self.channel_mode == ChannelMode::MidSide;
+        let gate = self.gate.is_enabled();
+
+        // 1. Gate, EQ & Gain (Apply to output buffer first by copying input)
         for i in 0..len {
-            let mut l = input_l[i];
-            let mut r = input_r[i];
+            let (mut l, mut r) = (input_l[i], input_r[i]);
+            if gate {
+                (l, r) = self.gate.process(l, r);
+            }
+
+            self.headroom_gain = self.headroom_coeff * self.headroom_gain
+                + (1.0 - self.headroom_coeff) * self.headroom_target;
+            l *= self.headroom_gain;
+            r *= self.headroom_gain;
+
+            // In mid/side mode the band sets see mid and side instead of left and right
+            if mid_side {
+                (l, r) = ((l + r) * 0.5, (l - r) * 0.5);
+            }
             
-            // Left Filters
-            for filter in &mut self.filters_l {
-                l = filter.process(l);
+            if let (Some(fir_l), Some(fir_r)) = (self.fir_l.as_mut(), self.fir_r.as_mut()) {
+                l = fir_l.process(l);
+                r = fir_r.process(r);
+            } else {
+                // Left Filters
+                for filter in &mut self.filters_l {
+                    l = filter.process(l);
+                }
+                // Right Filters
+                for filter in &mut self.filters_r {
+                    r = filter.process(r);
+                }
+                for filter in &mut self.retiring_l {
+                    l = filter.process(l);
+                }
+                for filter in &mut self.retiring_r {
+                    r = filter.process(r);
+                }
             }
-            // Right Filters
-            for filter in &mut self.filters_r {
-                r = filter.process(r);
+
+            if mid_side {
+                (l, r) = (l + r, l - r);
             }
             
             // Gain
-            output_l[i] = l * self.gain;
-            output_r[i] = r * self.gain;
+            output_l[i] = l * self.gain * self.preamp;
+            output_r[i] = r * self.gain * self.preamp;
             
             // Analysis Mixing (Mono downmix post-EQ/Gain for detection)
             if self.analysis_pos < 4096 {
@@ -130,13 +793,13 @@ impl JuraganAudioDSP {
             }
         }
         
-        // 2. Analysis Trigger (if buffer full and SBR enabled)
+        // Removed bands are dropped once they are flat
+        self.retiring_l.retain(|band| !band.is_faded_out());
+        self.retiring_r.retain(|band| !band.is_faded_out());
+
+        // 2. Source analysis (cutoff detection and SBR trigger) once the buffer is full
         if self.analysis_pos >= 4096 {
-            if self.sbr.is_enabled() {
-                self.perform_sbr_analysis();
-            } else {
-                self.sbr_active_timer = 0;
-            }
+            self.analyze_source();
             self.analysis_pos = 0;
         }
         
@@ -154,78 +817,111 @@ impl JuraganAudioDSP {
         // 3. SBR (In-place on output)
         self.sbr.process_block(output_l, output_r, sbr_is_active);
         
-        // 4. Dynamics (In-place on output)
+        // 4. Multiband compressor (In-place on output)
+        self.multiband.process_block(output_l, output_r);
+
+        // 5. Dynamics (In-place on output)
         self.dynamics.process_block(output_l, output_r);
     }
     
-    fn perform_sbr_analysis(&mut self) {
-        let magnitudes = self.fft_analyzer.process(&self.analysis_buffer);
-        let bin_size = self.sample_rate / 4096.0;
-        
-        // Reference Zone: 2kHz - 4.5kHz (Reference for average music volume)
-        let ref_start = (2000.0 / bin_size) as usize;
-        let ref_end = (4500.0 / bin_size) as usize;
-        
-        // Dead Zone check: 8kHz - 12kHz (Where brick-wall compression is most obvious)
-        let cut_start = (8000.0 / bin_size) as usize;
-        let cut_end = (12000.0 / bin_size) as usize;
+    fn bands_changed(&mut self) {
+        // Derived state is refreshed from process_stereo rather than once per setter call
+        self.bands_dirty = true;
+    }
 
-        // Presence check: 14kHz - 18kHz (If there is energy here, it's NOT a brick-wall file)
-        let high_start = (14000.0 / bin_size) as usize;
-        let high_end = (18000.0 / bin_size) as usize;
-        
-        let mut sum_ref = 0.0;
-        let mut count_ref = 0;
-        for i in ref_start..ref_end.min(magnitudes.len()) {
-            sum_ref += magnitudes[i];
-            count_ref += 1;
+    fn refresh_bands(&mut self) {
+        // One refresh (linear-phase redesign and headroom scan) runs at a time, spread over
+        // several blocks. Changes made while it runs are picked up by the next one, so a
+        // dragged slider costs each block the same bounded amount of work.
+        if self.bands_dirty && !self.refresh.is_running() {
+            let linked = self.channel_mode == ChannelMode::Linked;
+            self.refresh.start(linked, self.linear_phase, self.auto_headroom);
+            self.bands_dirty = false;
         }
-        
-        let mut sum_cut = 0.0;
-        let mut count_cut = 0;
-        for i in cut_start..cut_end.min(magnitudes.len()) {
-            sum_cut += magnitudes[i];
-            count_cut += 1;
+        if !self.refresh.step(&self.filters_l, &self.filters_r) {
+            return;
         }
 
-        let mut sum_high = 0.0;
-        let mut count_high = 0;
-        for i in high_start..high_end.min(magnitudes.len()) {
-            sum_high += magnitudes[i];
-            count_high += 1;
+        // Either setting may have been switched off while the refresh ran
+        if let Some(gain) = self.refresh.headroom_gain().filter(|_| self.auto_headroom) {
+            self.headroom_target = gain;
         }
-        
-        let avg_ref = if count_ref > 0 { sum_ref / count_ref as f32 } else { 0.0001 };
-        let avg_cut = if count_cut > 0 { sum_cut / count_cut as f32 } else { 0.0 };
-        let avg_high = if count_high > 0 { sum_high / count_high as f32 } else { 0.0 };
-        
-        // Detection Trigger:
-        // 1. Must have active music signal (avg_ref > floor)
-        // 2. Must NOT have significant energy in the high-frequency presence zone (High Quality check)
-        // 3. Must have a massive energy drop in the cut-start zone (Brick-wall check)
-        
-        if avg_ref > 0.002 { 
-            let hifi_ratio = avg_high / avg_ref;
-            let cutoff_ratio = avg_cut / avg_ref;
-
-            // Strict Hi-Fi exclusion: if there's any real activity > 14kHz, it's not a lo-fi file.
-            if hifi_ratio > 0.02 {
-                self.sbr_active_timer = 0; // Immediate disable
+        if let (Some(taps_l), Some(taps_r)) = (self.refresh.taps(0), self.refresh.taps(1)) {
+            if !self.linear_phase {
                 return;
             }
-
-            // Strict Cutoff detection: Only trigger if the 8k+ region is nearly dead (< 4% of reference)
-            if cutoff_ratio < 0.04 { 
-                self.sbr_active_timer = (self.sample_rate * 5.0) as usize;
-            } else {
-                // If it's not brick-walled, don't hold it. 
-                // We keep the timer if it was already active to avoid flickering, 
-                // but we won't refresh it.
+            match (self.fir_l.as_mut(), self.fir_r.as_mut()) {
+                (Some(fir_l), Some(fir_r)) => {
+                    fir_l.set_taps(taps_l);
+                    fir_r.set_taps(taps_r);
+                }
+                _ => {
+                    self.fir_l = Some(FirFilter::new(taps_l));
+                    self.fir_r = Some(FirFilter::new(taps_r));
+                    self.retiring_l.clear();
+                    self.retiring_r.clear();
+                }
             }
         }
     }
 
+    fn retire_bands(&mut self, bands_l: Vec<EqBand>, bands_r: Vec<EqBand>) {
+        // The linear-phase FIR crossfades to the new taps by itself
+        if self.fir_l.is_some() {
+            return;
+        }
+        for mut band in bands_l {
+            band.fade_out();
+            self.retiring_l.push(band);
+        }
+        for mut band in bands_r {
+            band.fade_out();
+            self.retiring_r.push(band);
+        }
+    }
+
+    fn channel_bands(&self, channel: usize) -> Option<&[EqBand]> {
+        match channel {
+            0 => Some(&self.filters_l),
+            1 => Some(&self.filters_r),
+            _ => None,
+        }
+    }
+
+    fn channel_bands_mut(&mut self, channel: usize) -> Option<&mut [EqBand]> {
+        match channel {
+            0 => Some(&mut self.filters_l),
+            1 => Some(&mut self.filters_r),
+            _ => None,
+        }
+    }
+
+    fn analyze_source(&mut self) {
+        let magnitudes = self.fft_analyzer.process(&self.analysis_buffer);
+        self.cutoff_detector.process(&magnitudes);
+        let cutoff = self.cutoff_detector.cutoff_hz();
+        self.sbr.set_cutoff(cutoff);
+
+        if self.sbr.is_enabled() && cutoff.is_some() {
+            // Refreshed on every analysis; only runs out if analysis stops coming
+            self.sbr_active_timer = (self.sample_rate * 5.0) as usize;
+        } else {
+            // The detector has its own hysteresis, a released cutoff means full band
+            self.sbr_active_timer = 0;
+        }
+    }
+
     pub fn get_fft(&mut self, input: &[f32]) -> Vec<f32> {
         self.fft_analyzer.process(input)
     }
 }
+
+fn write_response(i: usize, norm: f64, arg: f64, magnitude: &mut [f32], phase: &mut [f32]) {
+    if let Some(m) = magnitude.get_mut(i) {
+        *m = norm as f32;
+    }
+    if let Some(p) = phase.get_mut(i) {
+        // Wrap to (-pi, pi] like Web Audio
+        *p = Complex::from_polar(1.0, arg).arg() as f32;
+    }
+}
@@ -1,6 +1,28 @@
 use wasm_bindgen::prelude::*;
+use rustfft::{Fft, FftPlanner, num_complex::Complex};
+use rustfft::num_traits::Zero;
 use std::f32::consts::PI;
-use crate::config::SBR_CONFIG;
+use std::sync::Arc;
+use crate::config::{ConfigError, SbrConfig, SBR_CONFIG};
+
+// How the missing highband is generated. Exciter is the original rectifier + noise
+// generator; Spectral copies real lowband content above the cutoff.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SbrMode {
+    Exciter = 0,
+    Spectral = 1,
+}
+
+// STFT frame and hop of the spectral mode. 75% overlap with Hann analysis and
+// synthesis windows; the dry signal is delayed by FRAME_SIZE to line up.
+const FRAME_SIZE: usize = 1024;
+const HOP_SIZE: usize = FRAME_SIZE / 4;
+// Patches move by multiples of this many bins. A shift of d bins then turns the phase
+// by 2*pi*d*HOP_SIZE/FRAME_SIZE per hop, whole turns, so shifted partials stay coherent.
+const SHIFT_STEP: usize = FRAME_SIZE / HOP_SIZE;
+// Sub-bands of the source octave whose levels are flattened before the tilt is applied
+const ENVELOPE_BANDS: usize = 4;
 
 // Simple IIR Lowpass for SBR Gen
 struct LowPassFilter {
@@ -62,16 +84,41 @@ impl Xorshift32 {
     }
 }
 
+// Level of the generated signal, ramped linearly between 0 and 1 so the highband
+// fades in and out when detection flips instead of switching abruptly.
+struct WetRamp {
+    level: f32,
+    up_step: f32,
+    down_step: f32,
+}
+
+impl WetRamp {
+    fn new() -> Self {
+        Self { level: 0.0, up_step: 1.0, down_step: 1.0 }
+    }
+
+    fn set_times(&mut self, fade_in_ms: f32, fade_out_ms: f32, sample_rate: f32) {
+        let step = |ms: f32| 1.0 / (ms / 1000.0 * sample_rate).max(1.0);
+        self.up_step = step(fade_in_ms);
+        self.down_step = step(fade_out_ms);
+    }
+
+    fn next(&mut self, active: bool) -> f32 {
+        self.level = if active {
+            (self.level + self.up_step).min(1.0)
+        } else {
+            (self.level - self.down_step).max(0.0)
+        };
+        self.level
+    }
+}
+
 // Per-channel state
 struct SbrChannelState {
     hp: HighPassFilter,
     lpf: LowPassFilter,
     synth_hp: HighPassFilter,
-    env_fast: f32,
-    env_slow: f32,
-    tail: f32,
-    noise_hp: f32,
-    noise_x1: f32,
+    level: f32, // Smoothed detection level, sets the channel's share of the noise
 }
 
 impl SbrChannelState {
@@ -80,12 +127,246 @@ impl SbrChannelState {
             hp: HighPassFilter::new(alpha_hpf),
             lpf: LowPassFilter::new(alpha_lpf),
             synth_hp: HighPassFilter::new(alpha_synth_hpf),
-            env_fast: 0.0,
-            env_slow: 0.0,
-            tail: 0.0,
-            noise_hp: 0.0,
-            noise_x1: 0.0,
+            level: 0.0,
+        }
+    }
+}
+
+struct SpectralChannel {
+    input: Vec<f32>,  // last FRAME_SIZE input samples, the next hop fills the tail
+    output: Vec<f32>, // overlap-add accumulator, the current hop is read from the front
+    dry: Vec<f32>,
+    dry_pos: usize,
+    spectrum: Vec<Complex<f32>>,
+}
+
+impl SpectralChannel {
+    fn new() -> Self {
+        Self {
+            input: vec![0.0; FRAME_SIZE],
+            output: vec![0.0; FRAME_SIZE],
+            dry: vec![0.0; FRAME_SIZE],
+            dry_pos: 0,
+            spectrum: vec![Complex::zero(); FRAME_SIZE],
+        }
+    }
+
+    fn reset(&mut self) {
+        self.input.fill(0.0);
+        self.output.fill(0.0);
+        self.dry.fill(0.0);
+        self.dry_pos = 0;
+    }
+}
+
+// Where the patch goes for the current cutoff, in bins.
+#[derive(Clone, Copy)]
+struct PatchPlan {
+    cutoff: usize,
+    top: usize,
+    source_low: usize,
+    width: usize,
+    first_shift: usize,
+    reference_low: usize,
+    reference_high: usize,
+}
+
+impl PatchPlan {
+    // Source bin copied into bin `k` (cutoff <= k < top)
+    fn source(&self, k: usize) -> usize {
+        self.source_low + (k - self.source_low - self.first_shift) % self.width
+    }
+}
+
+// Bandwidth extension in the STFT domain: the octave below the source high edge is
+// copied up into the empty region above the cutoff set by the detector, repeatedly if needed,
+// its envelope flattened and re-shaped to continue the level just below the cutoff
+// with a natural downward tilt, and the result overlap-added onto the dry signal.
+struct SpectralSbr {
+    fft: Arc<dyn Fft<f32>>,
+    ifft: Arc<dyn Fft<f32>>,
+    scratch: Vec<Complex<f32>>,
+    window: Vec<f32>,
+    patch: Vec<Complex<f32>>,
+    gains: Vec<f32>,
+    left: SpectralChannel,
+    right: SpectralChannel,
+    hop_pos: usize,
+
+    // First empty bin, None until a cutoff has been detected
+    cutoff_bin: Option<usize>,
+    bin_hz: f32,
+    config: SbrConfig,
+}
+
+impl SpectralSbr {
+    fn new(sample_rate: f32) -> Self {
+        let mut planner = FftPlanner::new();
+        let fft = planner.plan_fft_forward(FRAME_SIZE);
+        let ifft = planner.plan_fft_inverse(FRAME_SIZE);
+        let scratch_len = fft.get_inplace_scratch_len().max(ifft.get_inplace_scratch_len());
+        // Periodic Hann, so the squared windows overlap-add to a constant
+        let window = (0..FRAME_SIZE)
+            .map(|n| 0.5 - 0.5 * (2.0 * PI * n as f32 / FRAME_SIZE as f32).cos())
+            .collect();
+        Self {
+            fft,
+            ifft,
+            scratch: vec![Complex::zero(); scratch_len],
+            window,
+            patch: vec![Complex::zero(); FRAME_SIZE],
+            gains: vec![0.0; FRAME_SIZE / 2],
+            left: SpectralChannel::new(),
+            right: SpectralChannel::new(),
+            hop_pos: 0,
+            cutoff_bin: None,
+            bin_hz: sample_rate / FRAME_SIZE as f32,
+            config: SBR_CONFIG,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.left.reset();
+        self.right.reset();
+        self.hop_pos = 0;
+    }
+
+    fn set_cutoff(&mut self, cutoff_hz: Option<f32>) {
+        self.cutoff_bin = cutoff_hz.map(|hz| ((hz / self.bin_hz).ceil() as usize).min(FRAME_SIZE / 2));
+    }
+
+    fn process(&mut self, input_l: &mut [f32], input_r: &mut [f32], gain: f32, active: bool, wet: &mut WetRamp) {
+        for (l, r) in input_l.iter_mut().zip(input_r.iter_mut()) {
+            let pos = self.hop_pos;
+            let level = wet.next(active);
+            *l = Self::push(&mut self.left, pos, *l, level);
+            *r = Self::push(&mut self.right, pos, *r, level);
+
+            self.hop_pos += 1;
+            if self.hop_pos == HOP_SIZE {
+                self.hop_pos = 0;
+                // Keep synthesizing until a fade-out has finished
+                self.process_frame(gain, active || wet.level > 0.0);
+            }
+        }
+    }
+
+    // Feeds one sample and returns the delayed dry sample plus the generated highband at `wet`.
+    fn push(channel: &mut SpectralChannel, pos: usize, sample: f32, wet: f32) -> f32 {
+        channel.input[FRAME_SIZE - HOP_SIZE + pos] = sample;
+        let dry = channel.dry[channel.dry_pos];
+        channel.dry[channel.dry_pos] = sample;
+        channel.dry_pos = (channel.dry_pos + 1) % FRAME_SIZE;
+        dry + channel.output[pos] * wet
+    }
+
+    fn process_frame(&mut self, gain: f32, synthesize: bool) {
+        let plan = if synthesize { self.patch_plan() } else { None };
+        for channel in [&mut self.left, &mut self.right] {
+            if plan.is_some() {
+                for ((bin, &sample), &w) in channel.spectrum.iter_mut().zip(&channel.input).zip(&self.window) {
+                    *bin = Complex::new(sample * w, 0.0);
+                }
+                self.fft.process_with_scratch(&mut channel.spectrum, &mut self.scratch);
+            }
+            channel.input.copy_within(HOP_SIZE.., 0);
         }
+
+        let plan = plan.filter(|plan| {
+            patch_gains(&self.left.spectrum, &self.right.spectrum, &mut self.gains, plan, &self.config, gain)
+        });
+        // Squared Hann at 75% overlap sums to 1.5, the inverse FFT is unnormalized
+        let scale = 1.0 / (1.5 * FRAME_SIZE as f32);
+        for channel in [&mut self.left, &mut self.right] {
+            channel.output.copy_within(HOP_SIZE.., 0);
+            channel.output[FRAME_SIZE - HOP_SIZE..].fill(0.0);
+
+            let Some(plan) = plan else { continue };
+            build_patch(&channel.spectrum, &mut self.patch, &self.gains, &plan);
+            self.ifft.process_with_scratch(&mut self.patch, &mut self.scratch);
+            for ((out, value), &w) in channel.output.iter_mut().zip(&self.patch).zip(&self.window) {
+                *out += value.re * w * scale;
+            }
+        }
+    }
+
+    fn patch_plan(&self) -> Option<PatchPlan> {
+        let cutoff = self.cutoff_bin?;
+        let top = self.bin(self.config.patch_top_hz).min(FRAME_SIZE / 2 - 1);
+        if cutoff + SHIFT_STEP >= top {
+            return None;
+        }
+        let source_high = self.bin(self.config.patch_source_high_hz).min(cutoff);
+        let source_low = self.bin(self.config.patch_source_low_hz).min(source_high / 2);
+        let width = (source_high - source_low) / SHIFT_STEP * SHIFT_STEP;
+        if width < SHIFT_STEP * ENVELOPE_BANDS {
+            return None;
+        }
+        Some(PatchPlan {
+            cutoff,
+            top,
+            source_low,
+            width,
+            first_shift: (cutoff - source_low) / SHIFT_STEP * SHIFT_STEP,
+            // Level reference just below the cutoff, clear of the encoder's lowpass slope
+            reference_low: cutoff * 6 / 10,
+            reference_high: cutoff * 9 / 10,
+        })
+    }
+
+    fn bin(&self, frequency: f32) -> usize {
+        ((frequency / self.bin_hz) as usize).min(FRAME_SIZE / 2)
+    }
+}
+
+// Per-bin gains of the patch, from the level of both channels together. Left and right
+// get the same gains, so every copied bin keeps the level and phase difference between
+// the channels it had in the source band: the highs take over the lowband's image.
+// Returns false when there is nothing to add.
+fn patch_gains(
+    left: &[Complex<f32>],
+    right: &[Complex<f32>],
+    gains: &mut [f32],
+    plan: &PatchPlan,
+    config: &SbrConfig,
+    gain: f32,
+) -> bool {
+    let mean = |range: std::ops::Range<usize>| {
+        let len = range.len().max(1) as f32;
+        let level: f32 = range.map(|k| (0.5 * (left[k].norm_sqr() + right[k].norm_sqr())).sqrt()).sum();
+        level / len
+    };
+    let reference = mean(plan.reference_low..plan.reference_high);
+    if reference < 1.0e-9 {
+        return false;
+    }
+    let reference_bin = 0.5 * (plan.reference_low + plan.reference_high) as f32;
+    let sub_width = plan.width / ENVELOPE_BANDS;
+    let sub_levels: [f32; ENVELOPE_BANDS] = std::array::from_fn(|j| {
+        let start = plan.source_low + j * sub_width;
+        let end = if j + 1 == ENVELOPE_BANDS { plan.source_low + plan.width } else { start + sub_width };
+        mean(start..end)
+    });
+    let max_boost = 10.0f32.powf(config.patch_max_boost_db / 20.0);
+
+    for (k, bin_gain) in gains.iter_mut().enumerate().take(plan.top).skip(plan.cutoff) {
+        let source = plan.source(k);
+        let sub_band = ((source - plan.source_low) / sub_width.max(1)).min(ENVELOPE_BANDS - 1);
+        let octaves = (k as f32 / reference_bin).log2();
+        let target = reference * 10.0f32.powf(-config.patch_tilt_db_per_octave * octaves / 20.0);
+        *bin_gain = (target / sub_levels[sub_band].max(1.0e-9)).min(max_boost) * gain;
+    }
+    true
+}
+
+// Fills `patch` with the shifted source bins of `spectrum` times `gains`, and their
+// mirror images.
+fn build_patch(spectrum: &[Complex<f32>], patch: &mut [Complex<f32>], gains: &[f32], plan: &PatchPlan) {
+    patch.fill(Complex::zero());
+    for k in plan.cutoff..plan.top {
+        let value = spectrum[plan.source(k)] * gains[k];
+        patch[k] = value;
+        patch[FRAME_SIZE - k] = value.conj();
     }
 }
 
@@ -95,7 +376,6 @@ pub struct SBRProcessor {
     right: SbrChannelState,
     
     // Shared constant params
-    alpha_hpf: f32,
     alpha_fast: f32,
     alpha_slow: f32,
     tail_decay: f32,
@@ -103,8 +383,21 @@ pub struct SBRProcessor {
     params_gain: f32,
     params_enabled: bool,
     
-    rng_left: Xorshift32,
-    rng_right: Xorshift32,
+    // Transient detection and noise are shared by both channels, so the generated
+    // highs stay as correlated as the lowband they come from
+    env_fast: f32,
+    env_slow: f32,
+    tail: f32,
+    noise_hp: f32,
+    noise_x1: f32,
+    rng: Xorshift32,
+
+    mode: SbrMode,
+    spectral: SpectralSbr,
+    sample_rate: f32,
+    config: SbrConfig,
+    cutoff_hz: Option<f32>,
+    wet: WetRamp,
 }
 
 #[wasm_bindgen]
@@ -113,17 +406,16 @@ impl SBRProcessor {
     pub fn new(sample_rate: f32) -> Self {
         let alpha_hpf = SBR_CONFIG.detection_hp_alpha; // Highpass for detection
         
-        // Lowpass cutoff at 18000Hz to avoid noisy ultra-high content.
-        let fc = 18000.0;
+        // Lowpass to avoid noisy ultra-high content.
+        let fc = SBR_CONFIG.synth_lp_cutoff_hz.min(sample_rate * 0.45);
         let alpha_lpf = 1.0 - (-2.0 * PI * fc / sample_rate).exp();
-        let synth_hp_fc = 6000.0;
+        let synth_hp_fc = SBR_CONFIG.synth_hp_cutoff_hz;
         let alpha_synth_hpf = (-2.0 * PI * synth_hp_fc / sample_rate).exp();
         
-        Self {
+        let mut sbr = Self {
             left: SbrChannelState::new(alpha_hpf, alpha_lpf, alpha_synth_hpf),
             right: SbrChannelState::new(alpha_hpf, alpha_lpf, alpha_synth_hpf),
             
-            alpha_hpf,
             alpha_fast: SBR_CONFIG.fast_env_alpha,
             alpha_slow: SBR_CONFIG.slow_env_alpha,
             tail_decay: SBR_CONFIG.tail_decay,
@@ -131,11 +423,27 @@ impl SBRProcessor {
             params_gain: 1.0,
             params_enabled: false,
             
-            rng_left: Xorshift32::new(12345),
-            rng_right: Xorshift32::new(54321),
-        }
+            env_fast: 0.0,
+            env_slow: 0.0,
+            tail: 0.0,
+            noise_hp: 0.0,
+            noise_x1: 0.0,
+            rng: Xorshift32::new(12345),
+
+            mode: SbrMode::Exciter,
+            spectral: SpectralSbr::new(sample_rate),
+            sample_rate,
+            config: SBR_CONFIG,
+            cutoff_hz: None,
+            wet: WetRamp::new(),
+        };
+        sbr.wet.set_times(SBR_CONFIG.fade_in_ms, SBR_CONFIG.fade_out_ms, sample_rate);
+        sbr
     }
     
+    /// Disabling fades the highband out like a detector release; in spectral mode the
+    /// delay line stays in the path, so toggling never switches between delayed and
+    /// undelayed audio.
     pub fn set_options(&mut self, enabled: bool, gain: f32) {
         self.params_enabled = enabled;
         self.params_gain = gain;
@@ -144,103 +452,289 @@ impl SBRProcessor {
     pub fn is_enabled(&self) -> bool {
         self.params_enabled
     }
+
+    /// Exciter (default) adds no latency; Spectral delays the signal by one STFT frame,
+    /// see `latency_samples`. The delay line starts empty after a switch.
+    pub fn set_mode(&mut self, mode: SbrMode) {
+        if mode != self.mode {
+            self.mode = mode;
+            self.spectral.reset();
+        }
+    }
+
+    pub fn get_mode(&self) -> SbrMode {
+        self.mode
+    }
+
+    /// Detected source cutoff; synthesis starts there. `None` keeps the spectral mode
+    /// silent and the exciter at its configured `synth_hp_cutoff_hz`.
+    pub fn set_cutoff(&mut self, cutoff_hz: Option<f32>) {
+        self.cutoff_hz = cutoff_hz;
+        self.spectral.set_cutoff(cutoff_hz);
+        self.update_synth_hp();
+    }
+
+    /// Replaces the whole tuning set. Filter states are kept, so this is safe while
+    /// playing. Nothing changes if the set fails validation.
+    pub fn set_config(&mut self, config: &SbrConfig) -> Result<(), ConfigError> {
+        config.validate()?;
+        self.config = *config;
+        self.spectral.config = *config;
+
+        self.alpha_fast = config.fast_env_alpha;
+        self.alpha_slow = config.slow_env_alpha;
+        self.tail_decay = config.tail_decay;
+
+        let fc = config.synth_lp_cutoff_hz.min(self.sample_rate * 0.45);
+        let alpha_lpf = 1.0 - (-2.0 * PI * fc / self.sample_rate).exp();
+        for channel in [&mut self.left, &mut self.right] {
+            channel.hp.alpha = config.detection_hp_alpha;
+            channel.lpf.alpha = alpha_lpf;
+        }
+        self.update_synth_hp();
+        self.wet.set_times(config.fade_in_ms, config.fade_out_ms, self.sample_rate);
+        Ok(())
+    }
+
+    pub fn get_config(&self) -> SbrConfig {
+        self.config
+    }
+
+    /// Current level of the generated highband, 0 (off) to 1 (fully engaged); in
+    /// between while fading after the detector has switched.
+    pub fn wet_level(&self) -> f32 {
+        self.wet.level
+    }
+
+    /// Delay added to the signal: one STFT frame in spectral mode, enabled or not.
+    pub fn latency_samples(&self) -> usize {
+        if self.mode == SbrMode::Spectral { FRAME_SIZE } else { 0 }
+    }
     
+    /// `sbr_active` is the detector's verdict; the generated signal fades toward it at the
+    /// configured rates. In spectral mode the delay line keeps running while inactive or
+    /// disabled so the delay stays constant; only the synthesis stops.
     pub fn process_block(&mut self, input_l: &mut [f32], input_r: &mut [f32], sbr_active: bool) {
-        if !self.params_enabled || !sbr_active {
-            return;
+        let active = self.params_enabled && sbr_active;
+        match self.mode {
+            SbrMode::Exciter if active || self.wet.level > 0.0 => {
+                self.process_exciter(input_l, input_r, active)
+            }
+            SbrMode::Exciter => {}
+            SbrMode::Spectral => {
+                self.spectral.process(input_l, input_r, self.params_gain, active, &mut self.wet)
+            }
         }
+    }
+}
+
+impl SBRProcessor {
+    fn update_synth_hp(&mut self) {
+        let synth_hp_fc = self.cutoff_hz.unwrap_or(self.config.synth_hp_cutoff_hz);
+        let alpha_synth_hpf = (-2.0 * PI * synth_hp_fc / self.sample_rate).exp();
+        self.left.synth_hp.alpha = alpha_synth_hpf;
+        self.right.synth_hp.alpha = alpha_synth_hpf;
+    }
 
-        let makeup = 0.8 * self.params_gain;
+    // Both channels are rectified with the polarity of the mid signal, so each keeps
+    // its own level and sign: a mono source gives mono highs, a panned one stays panned,
+    // and only genuinely wide material produces wide highs.
+    fn process_exciter(&mut self, input_l: &mut [f32], input_r: &mut [f32], sbr_active: bool) {
         let block_size = input_l.len().min(input_r.len());
 
         for i in 0..block_size {
             let l = input_l[i];
             let r = input_r[i];
+            let makeup = 0.8 * self.params_gain * self.wet.next(sbr_active);
             
-            // --- Left Channel Process ---
             // 1. High Pass Filter (Detection)
-            let hp_l = self.alpha_hpf * (self.left.hp.y1 + l - self.left.hp.x1);
-            self.left.hp.x1 = l;
-            self.left.hp.y1 = hp_l;
+            let hp_l = self.left.hp.process(l);
+            let hp_r = self.right.hp.process(r);
             
             // 2. Harmonic Generator
-            let harm_l = hp_l.abs();
+            let polarity = (hp_l + hp_r).signum();
+            let harm_l = hp_l * polarity;
+            let harm_r = hp_r * polarity;
+            self.left.level = self.alpha_fast * self.left.level + (1.0 - self.alpha_fast) * hp_l.abs();
+            self.right.level = self.alpha_fast * self.right.level + (1.0 - self.alpha_fast) * hp_r.abs();
             
-            // 3. Transient Detection
-            self.left.env_fast = self.alpha_fast * self.left.env_fast + (1.0 - self.alpha_fast) * harm_l;
-            self.left.env_slow = self.alpha_slow * self.left.env_slow + (1.0 - self.alpha_slow) * harm_l;
+            // 3. Transient Detection (linked, on the louder channel)
+            let harm = hp_l.abs().max(hp_r.abs());
+            self.env_fast = self.alpha_fast * self.env_fast + (1.0 - self.alpha_fast) * harm;
+            self.env_slow = self.alpha_slow * self.env_slow + (1.0 - self.alpha_slow) * harm;
             
             // 4. Pulse Stretcher
-            let trigger_l = (self.left.env_fast - self.left.env_slow * 1.6).max(0.0);
+            let trigger = (self.env_fast - self.env_slow * self.config.transient_ratio).max(0.0);
             
-            if trigger_l > self.left.tail {
-                self.left.tail = trigger_l;
+            if trigger > self.tail {
+                self.tail = trigger;
             } else {
-                self.left.tail *= self.tail_decay;
+                self.tail *= self.tail_decay;
             }
             
             // 5. Synthesis
-            let syn_gain_l = (self.left.tail * 15.0).min(1.0);
-            
-            // Noise
-            let n_l = self.rng_left.next_f32();
-            self.left.noise_hp = 0.3 * (self.left.noise_hp + n_l - self.left.noise_x1);
-            self.left.noise_x1 = n_l;
+            let syn_gain = (self.tail * self.config.synthesis_gain).min(1.0) * makeup;
             
-            // Generated Signal (Harmonics + Noise)
-            let mut generated_l = (harm_l * syn_gain_l * makeup) + (self.left.noise_hp * syn_gain_l * makeup * 0.15);
+            // Noise, split by channel level
+            let n = self.rng.next_f32();
+            self.noise_hp = 0.3 * (self.noise_hp + n - self.noise_x1);
+            self.noise_x1 = n;
+            let loudest = self.left.level.max(self.right.level).max(1.0e-9);
             
-            // Highpass to focus on missing band
-            generated_l = self.left.synth_hp.process(generated_l);
+            for (channel, harm, input) in [
+                (&mut self.left, harm_l, &mut input_l[i]),
+                (&mut self.right, harm_r, &mut input_r[i]),
+            ] {
+                // Generated Signal (Harmonics + Noise)
+                let noise = self.noise_hp * 0.15 * channel.level / loudest;
+                let mut generated = (harm + noise) * syn_gain;
+                
+                // Highpass to focus on missing band, then LPF
+                generated = channel.synth_hp.process(generated);
+                generated = channel.lpf.process(generated);
+                
+                *input += generated;
+            }
+        }
+    }
+}
 
-            // LPF
-            generated_l = self.left.lpf.process(generated_l);
-            
-            // Mix Left
-            input_l[i] = l + generated_l;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
+    const SAMPLE_RATE: f32 = 48000.0;
+    const CUTOFF_HZ: f32 = 12000.0;
 
-            // --- Right Channel Process ---
-            // 1. High Pass Filter
-            let hp_r = self.alpha_hpf * (self.right.hp.y1 + r - self.right.hp.x1);
-            self.right.hp.x1 = r;
-            self.right.hp.y1 = hp_r;
-            
-            // 2. Harmonic Generator
-            let harm_r = hp_r.abs();
-            
-            // 3. Transient Detection
-            self.right.env_fast = self.alpha_fast * self.right.env_fast + (1.0 - self.alpha_fast) * harm_r;
-            self.right.env_slow = self.alpha_slow * self.right.env_slow + (1.0 - self.alpha_slow) * harm_r;
-            
-            // 4. Pulse Stretcher
-            let trigger_r = (self.right.env_fast - self.right.env_slow * 1.6).max(0.0);
-            
-            if trigger_r > self.right.tail {
-                self.right.tail = trigger_r;
-            } else {
-                self.right.tail *= self.tail_decay;
-            }
-            
-            // 5. Synthesis
-            let syn_gain_r = (self.right.tail * 15.0).min(1.0);
-            
-            // Noise
-            let n_r = self.rng_right.next_f32();
-            self.right.noise_hp = 0.3 * (self.right.noise_hp + n_r - self.right.noise_x1);
-            self.right.noise_x1 = n_r;
-            
-            // Generated Signal
-            let mut generated_r = (harm_r * syn_gain_r * makeup) + (self.right.noise_hp * syn_gain_r * makeup * 0.15);
-            
-            // Highpass to focus on missing band
-            generated_r = self.right.synth_hp.process(generated_r);
+    fn tone(freq: f32, n: usize) -> f32 {
+        (2.0 * PI * freq * n as f32 / SAMPLE_RATE).sin()
+    }
 
-            // LPF
-            generated_r = self.right.lpf.process(generated_r);
-            
-            // Mix Right
-            input_r[i] = r + generated_r;
+    // Processes both channels in 128-sample blocks with the detector verdict `active`.
+    fn run(sbr: &mut SBRProcessor, left: &mut [f32], right: &mut [f32], active: bool) {
+        for (l, r) in left.chunks_mut(128).zip(right.chunks_mut(128)) {
+            sbr.process_block(l, r, active);
+        }
+    }
+
+    fn spectral() -> SBRProcessor {
+        let mut sbr = SBRProcessor::new(SAMPLE_RATE);
+        sbr.set_mode(SbrMode::Spectral);
+        sbr.set_options(true, 1.0);
+        sbr.set_cutoff(Some(CUTOFF_HZ));
+        sbr
+    }
+
+    // Share of the energy of `signal` above `freq`.
+    fn energy_above(signal: &[f32], freq: f32) -> f32 {
+        let mut spectrum: Vec<Complex<f32>> = signal.iter().map(|&x| Complex::new(x, 0.0)).collect();
+        FftPlanner::new().plan_fft_forward(spectrum.len()).process(&mut spectrum);
+        let half = &spectrum[..spectrum.len() / 2];
+        let split = (freq / SAMPLE_RATE * spectrum.len() as f32) as usize;
+        let above: f32 = half[split..].iter().map(|bin| bin.norm_sqr()).sum();
+        above / half.iter().map(|bin| bin.norm_sqr()).sum::<f32>()
+    }
+
+    #[test]
+    fn spectral_mode_fills_in_above_the_cutoff() {
+        // A tone in the patch source band and one in the level reference band below the cutoff
+        let input: Vec<f32> = (0..SAMPLE_RATE as usize)
+            .map(|n| 0.3 * tone(6000.0, n) + 0.3 * tone(9000.0, n))
+            .collect();
+        assert!(energy_above(&input, CUTOFF_HZ) < 1.0e-6);
+
+        let mut sbr = spectral();
+        let (mut left, mut right) = (input.clone(), input.clone());
+        run(&mut sbr, &mut left, &mut right, true);
+        assert_eq!(left, right);
+
+        // Past the fade-in, everything beyond the delayed dry signal is highband
+        let latency = sbr.latency_samples();
+        let generated: Vec<f32> = (SAMPLE_RATE as usize / 2..SAMPLE_RATE as usize)
+            .map(|n| left[n] - input[n - latency])
+            .collect();
+        let rms = |x: &[f32]| (x.iter().map(|x| x * x).sum::<f32>() / x.len() as f32).sqrt();
+        assert!(rms(&generated) > 0.01, "generated rms {}", rms(&generated));
+        assert!(energy_above(&generated, CUTOFF_HZ) > 0.99);
+    }
+
+    #[test]
+    fn disabled_processor_is_a_pure_delay() {
+        let mut seed = 0x1234_5678u32;
+        let input: Vec<f32> = (0..SAMPLE_RATE as usize / 4)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                seed as f32 / u32::MAX as f32 - 0.5
+            })
+            .collect();
+
+        for mode in [SbrMode::Exciter, SbrMode::Spectral] {
+            let mut sbr = spectral();
+            sbr.set_mode(mode);
+            sbr.set_options(false, 1.0);
+            let (mut left, mut right) = (input.clone(), input.clone());
+            run(&mut sbr, &mut left, &mut right, true);
+
+            let latency = sbr.latency_samples();
+            assert!(left[..latency].iter().all(|&x| x == 0.0));
+            assert_eq!(left[latency..], input[..input.len() - latency], "{mode:?}");
+            assert_eq!(left, right);
+            assert_eq!(sbr.wet_level(), 0.0);
+        }
+    }
+
+    #[test]
+    fn wet_level_ramps_over_the_fade_times() {
+        let config = SbrConfig { fade_in_ms: 100.0, fade_out_ms: 200.0, ..SbrConfig::new() };
+        let fade_in = (0.1 * SAMPLE_RATE) as usize;
+        let fade_out = (0.2 * SAMPLE_RATE) as usize;
+        for mode in [SbrMode::Exciter, SbrMode::Spectral] {
+            let mut sbr = spectral();
+            sbr.set_mode(mode);
+            sbr.set_config(&config).unwrap();
+            let mut block = |active: bool, samples: usize| {
+                let (mut left, mut right) = (vec![0.1; samples], vec![0.1; samples]);
+                run(&mut sbr, &mut left, &mut right, active);
+                sbr.wet_level()
+            };
+
+            // Linear in both directions, arriving at the end (to rounding) after the fade time
+            assert!((block(true, fade_in / 2) - 0.5).abs() < 1.0e-3, "{mode:?}");
+            assert!((block(true, fade_in / 2) - 1.0).abs() < 1.0e-3, "{mode:?}");
+            assert_eq!(block(true, 2), 1.0);
+            assert!((block(false, fade_out / 4) - 0.75).abs() < 1.0e-3, "{mode:?}");
+            assert!(block(false, fade_out * 3 / 4) < 1.0e-3, "{mode:?}");
+            assert_eq!(block(false, 2), 0.0);
+        }
+    }
+
+    #[test]
+    fn generated_highs_keep_the_stereo_image() {
+        // Tone bursts, so the exciter's transient detector fires too
+        let input: Vec<f32> = (0..SAMPLE_RATE as usize)
+            .map(|n| {
+                let on = if (n / 2400).is_multiple_of(2) { 0.3 } else { 0.0 };
+                on * (tone(6000.0, n) + tone(9000.0, n))
+            })
+            .collect();
+
+        for mode in [SbrMode::Exciter, SbrMode::Spectral] {
+            let mut sbr = spectral();
+            sbr.set_mode(mode);
+            let (mut left, mut right) = (input.clone(), input.clone());
+            run(&mut sbr, &mut left, &mut right, true);
+            assert_eq!(left, right, "{mode:?}: mono in, mono out");
+
+            let mut sbr = spectral();
+            sbr.set_mode(mode);
+            let (mut left, mut right) = (input.clone(), vec![0.0; input.len()]);
+            run(&mut sbr, &mut left, &mut right, true);
+            assert!(right.iter().all(|&x| x == 0.0), "{mode:?}: highs leaked into the silent channel");
+            let latency = sbr.latency_samples();
+            let generated = (latency..input.len()).map(|n| (left[n] - input[n - latency]).abs());
+            assert!(generated.fold(0.0f32, f32::max) > 1.0e-2, "{mode:?}: nothing generated");
         }
     }
 }
@@ -236,12 +236,15 @@ class JuraganAudioProcessor extends AudioWorkletProcessor {
 
                 // Get reduction and SBR status from WASM
                 let reductionDb = this.wasmDSP.get_reduction_db();
+                // Output true peak (dBTP) over the same interval; older builds lack the getter
+                let truePeakDb = this.wasmDSP.get_true_peak_db ? this.wasmDSP.get_true_peak_db() : undefined;
                 let sbrActive = this.wasmDSP.is_sbr_active();
 
                 this.port.postMessage({
                     type: 'fftData',
                     data: fftData,
                     limiterReduction: reductionDb,
+                    truePeakDb: truePeakDb,
                     sbrActive: sbrActive
                 });
             }